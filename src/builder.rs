@@ -0,0 +1,338 @@
+use crate::channels::PheromoneChannels;
+use crate::components::{CoarsePositionComponent, PositionComponent};
+use crate::config::SimulationConfig;
+use crate::entities::EntityType;
+use crate::entity_store::EntityStore;
+use crate::game::{Game, Topology};
+use rand::prelude::SeedableRng;
+use rand::Rng;
+use rand_chacha::ChaCha20Rng;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// Attempts to find a free spot for a randomly placed ant.
+const RANDOM_PLACEMENT_ATTEMPTS: u32 = 100;
+
+/// Why a `GameBuilder` couldn't build its world.
+#[derive(Debug, PartialEq)]
+pub enum BuildError {
+    OutOfBounds {
+        entity_type: EntityType,
+        pos: PositionComponent,
+    },
+    /// Only ants can share a cell, and never with a wall.
+    Overlap {
+        entity_type: EntityType,
+        other: EntityType,
+        pos: PositionComponent,
+    },
+    /// A sugar source that would run out before anything was taken.
+    EmptySugar { pos: PositionComponent },
+    /// No room left in a rectangle to place ants randomly.
+    NoFreeCell {
+        min: PositionComponent,
+        max: PositionComponent,
+    },
+    /// The config fails `SimulationConfig::check`.
+    InvalidConfig(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::OutOfBounds { entity_type, pos } => {
+                let (x, y) = pos.to_f64();
+                write!(f, "{entity_type:?} at {x}, {y} is outside of the world")
+            }
+            BuildError::Overlap {
+                entity_type,
+                other,
+                pos,
+            } => {
+                let (x, y) = pos.to_f64();
+                write!(f, "{entity_type:?} at {x}, {y} overlaps a {other:?}")
+            }
+            BuildError::EmptySugar { pos } => {
+                let (x, y) = pos.to_f64();
+                write!(f, "Sugar at {x}, {y} has no items")
+            }
+            BuildError::NoFreeCell { min, max } => {
+                let ((min_x, min_y), (max_x, max_y)) = (min.to_f64(), max.to_f64());
+                write!(
+                    f,
+                    "no free cell for an ant between {min_x}, {min_y} and {max_x}, {max_y}"
+                )
+            }
+            BuildError::InvalidConfig(error) => write!(f, "invalid config: {error}"),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+enum Placement {
+    At {
+        entity_type: EntityType,
+        pos: PositionComponent,
+        items: Option<u32>,
+    },
+    RandomAnts {
+        min: PositionComponent,
+        max: PositionComponent,
+        count: u32,
+    },
+}
+
+/// Describes a world and checks it before creating the `Game`.
+/// Entities are created in the order they were added.
+pub struct GameBuilder {
+    width: f64,
+    height: f64,
+    topology: Topology,
+    seed: u64,
+    config: SimulationConfig,
+    placements: Vec<Placement>,
+}
+
+impl GameBuilder {
+    #[must_use]
+    pub fn new(width: f64, height: f64) -> Self {
+        Self {
+            width,
+            height,
+            topology: Topology::Bounded,
+            seed: 0,
+            config: SimulationConfig::default(),
+            placements: vec![],
+        }
+    }
+
+    pub fn topology(&mut self, topology: Topology) -> &mut Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Seeds the simulation, and the placement of random ants.
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn config(&mut self, config: SimulationConfig) -> &mut Self {
+        self.config = config;
+        self
+    }
+
+    fn place(&mut self, entity_type: EntityType, x: f64, y: f64, items: Option<u32>) -> &mut Self {
+        self.placements.push(Placement::At {
+            entity_type,
+            pos: PositionComponent::new(x, y),
+            items,
+        });
+        self
+    }
+
+    pub fn ant(&mut self, x: f64, y: f64) -> &mut Self {
+        self.place(EntityType::Ant, x, y, None)
+    }
+
+    /// `count` ants on random free spots between `min` and `max`.
+    pub fn ants_in_rect(&mut self, min: (f64, f64), max: (f64, f64), count: u32) -> &mut Self {
+        self.placements.push(Placement::RandomAnts {
+            min: PositionComponent::new(min.0, min.1),
+            max: PositionComponent::new(max.0, max.1),
+            count,
+        });
+        self
+    }
+
+    /// `count` ants on random free spots anywhere in the world.
+    pub fn random_ants(&mut self, count: u32) -> &mut Self {
+        let max = (self.width - 0.01, self.height - 0.01);
+        self.ants_in_rect((0.0, 0.0), max, count)
+    }
+
+    pub fn base(&mut self, x: f64, y: f64) -> &mut Self {
+        self.place(EntityType::Base, x, y, None)
+    }
+
+    /// A sugar source that never runs out.
+    pub fn sugar(&mut self, x: f64, y: f64) -> &mut Self {
+        self.place(EntityType::Sugar, x, y, None)
+    }
+
+    /// A sugar source that disappears once `items` have been taken,
+    /// `items` has to be at least 1.
+    pub fn sugar_with_quantity(&mut self, x: f64, y: f64, items: u32) -> &mut Self {
+        self.place(EntityType::Sugar, x, y, Some(items))
+    }
+
+    pub fn wall(&mut self, x: f64, y: f64) -> &mut Self {
+        self.place(EntityType::Wall, x, y, None)
+    }
+
+    /// Walls on every cell the line from `from` to `to` crosses.
+    pub fn wall_line(&mut self, from: (f64, f64), to: (f64, f64)) -> &mut Self {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as u32;
+        let mut centers = vec![];
+        for step in 0..=steps {
+            let t = f64::from(step) / f64::from(steps);
+            let (x, y) = (from.0 + dx * t, from.1 + dy * t);
+            let center = PositionComponent::new(x.floor() + 0.5, y.floor() + 0.5);
+            if !centers.contains(&center) {
+                centers.push(center);
+            }
+        }
+
+        for center in centers {
+            let (x, y) = center.to_f64();
+            self.wall(x, y);
+        }
+        self
+    }
+
+    /// Walls on every cell between the cells of `min` and `max`.
+    pub fn wall_rect(&mut self, min: (f64, f64), max: (f64, f64)) -> &mut Self {
+        for y in min.1.floor() as i32..=max.1.floor() as i32 {
+            for x in min.0.floor() as i32..=max.0.floor() as i32 {
+                self.wall(f64::from(x) + 0.5, f64::from(y) + 0.5);
+            }
+        }
+        self
+    }
+
+    /// The walls of Deneubourg's double bridge experiment in the top
+    /// left 5x5 cells, leaving a short and a long route between the
+    /// middle of the left and right edges.
+    pub fn deneubourg_walls(&mut self) -> &mut Self {
+        self.wall_line((0.5, 0.5), (4.5, 0.5))
+            .wall(0.5, 1.5)
+            .wall(4.5, 1.5)
+            .wall(2.5, 2.5)
+            .wall(0.5, 3.5)
+            .wall(4.5, 3.5)
+            .wall_line((0.5, 4.5), (4.5, 4.5))
+    }
+
+    fn is_in_bounds(&self, pos: &PositionComponent) -> bool {
+        let (x, y) = pos.to_f64();
+        x >= 0.0 && y >= 0.0 && x < self.width && y < self.height
+    }
+
+    /// Places the random ants and checks that everything fits.
+    fn resolve(&self) -> Result<Vec<(EntityType, PositionComponent, Option<u32>)>, BuildError> {
+        let mut rng = ChaCha20Rng::seed_from_u64(self.seed);
+        let walls: Vec<CoarsePositionComponent> = self
+            .placements
+            .iter()
+            .filter_map(|placement| match placement {
+                Placement::At {
+                    entity_type: EntityType::Wall,
+                    pos,
+                    ..
+                } => Some(CoarsePositionComponent::from(pos)),
+                _ => None,
+            })
+            .collect();
+
+        let mut entities = vec![];
+        for placement in &self.placements {
+            match placement {
+                Placement::At {
+                    entity_type,
+                    pos,
+                    items,
+                } => entities.push((*entity_type, pos.clone(), *items)),
+                Placement::RandomAnts { min, max, count } => {
+                    let ((min_x, min_y), (max_x, max_y)) = (min.to_f64(), max.to_f64());
+                    for _ in 0..*count {
+                        let pos = (0..RANDOM_PLACEMENT_ATTEMPTS)
+                            .map(|_| {
+                                PositionComponent::rounded(
+                                    rng.gen_range(min_x, max_x.max(min_x + 0.01)),
+                                    rng.gen_range(min_y, max_y.max(min_y + 0.01)),
+                                    self.config.position_resolution,
+                                )
+                            })
+                            .find(|pos| {
+                                self.is_in_bounds(pos)
+                                    && !walls.contains(&CoarsePositionComponent::from(pos))
+                            })
+                            .ok_or_else(|| BuildError::NoFreeCell {
+                                min: min.clone(),
+                                max: max.clone(),
+                            })?;
+                        entities.push((EntityType::Ant, pos, None));
+                    }
+                }
+            }
+        }
+
+        let mut cells: HashMap<CoarsePositionComponent, EntityType> = HashMap::new();
+        for (entity_type, pos, items) in &entities {
+            if *items == Some(0) {
+                return Err(BuildError::EmptySugar { pos: pos.clone() });
+            }
+
+            if !self.is_in_bounds(pos) {
+                return Err(BuildError::OutOfBounds {
+                    entity_type: *entity_type,
+                    pos: pos.clone(),
+                });
+            }
+
+            let cell = CoarsePositionComponent::from(pos);
+            if let Some(other) = cells.get(&cell) {
+                let allowed = *entity_type != EntityType::Wall
+                    && *other != EntityType::Wall
+                    && (*entity_type == EntityType::Ant || *other == EntityType::Ant);
+                if !allowed {
+                    return Err(BuildError::Overlap {
+                        entity_type: *entity_type,
+                        other: *other,
+                        pos: pos.clone(),
+                    });
+                }
+            }
+
+            // Ants don't keep anything else off their cell
+            if *entity_type == EntityType::Ant {
+                cells.entry(cell).or_insert(EntityType::Ant);
+            } else {
+                cells.insert(cell, *entity_type);
+            }
+        }
+
+        Ok(entities)
+    }
+
+    /// A game with everything added to the builder.
+    ///
+    /// # Errors
+    ///
+    /// If the config is invalid or the entities don't fit the world,
+    /// see `BuildError`.
+    pub fn build(&self) -> Result<Game, BuildError> {
+        self.config.check().map_err(BuildError::InvalidConfig)?;
+        let entities = self.resolve()?;
+
+        let mut store = EntityStore::bounded(self.width, self.height);
+        store.pheromone_channels =
+            PheromoneChannels::from_config(&self.config).map_err(BuildError::InvalidConfig)?;
+        let mut game = Game::init(store, self.width, self.height, self.config.clone());
+        game.set_seed(self.seed);
+        game.topology = self.topology;
+
+        for (entity_type, pos, items) in entities {
+            let id = game.entity_store.create_entity(entity_type);
+            game.entity_store.update_position(id, &pos);
+            if let Some(edible) = game.entity_store.edibles.get_mut(&id) {
+                edible.items = items;
+            }
+        }
+
+        Ok(game)
+    }
+}
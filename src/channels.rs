@@ -0,0 +1,273 @@
+use crate::config::SimulationConfig;
+use crate::entities::EntityType;
+use colored::Color;
+use std::fmt;
+use std::str::FromStr;
+
+/// How colors of declared channels are written.
+const COLOR_NAMES: [(Color, &str); 16] = [
+    (Color::Black, "black"),
+    (Color::Red, "red"),
+    (Color::Green, "green"),
+    (Color::Yellow, "yellow"),
+    (Color::Blue, "blue"),
+    (Color::Magenta, "magenta"),
+    (Color::Cyan, "cyan"),
+    (Color::White, "white"),
+    (Color::BrightBlack, "bright_black"),
+    (Color::BrightRed, "bright_red"),
+    (Color::BrightGreen, "bright_green"),
+    (Color::BrightYellow, "bright_yellow"),
+    (Color::BrightBlue, "bright_blue"),
+    (Color::BrightMagenta, "bright_magenta"),
+    (Color::BrightCyan, "bright_cyan"),
+    (Color::BrightWhite, "bright_white"),
+];
+
+/// Handle to a registered pheromone channel.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PheromoneChannel(usize);
+
+impl PheromoneChannel {
+    /// Laid by ants looking for food, leads to sugar.
+    pub const FOOD: Self = Self(0);
+    /// Laid by ants carrying food, leads back to the base.
+    pub const BASE: Self = Self(1);
+    /// Laid at dead ends, avoided by ants following a trail.
+    pub const REPELLENT: Self = Self(2);
+
+    /// Names of the channels above, which have to be registered first
+    /// and in this order.
+    pub const BUILTIN_NAMES: [&'static str; 3] = ["food", "base", "repellent"];
+
+    #[must_use]
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+pub struct PheromoneChannelSettings {
+    pub name: String,
+    /// Strength a pheromone in this channel loses every tick.
+    pub evaporation_rate: u32,
+    /// Pheromones on a cell with one of these entity types don't evaporate.
+    pub evaporation_exempt_at: Vec<EntityType>,
+    /// Strength multipliers for pheromones released on a cell with
+    /// one of these entity types.
+    pub boosts: Vec<(EntityType, u32)>,
+    pub color: Color,
+}
+
+impl PheromoneChannelSettings {
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            evaporation_rate: 1,
+            evaporation_exempt_at: vec![EntityType::Sugar, EntityType::Base],
+            boosts: vec![],
+            color: Color::White,
+        }
+    }
+}
+
+/// A channel registered after the built-in ones, written as
+/// `name:evaporation_rate:color`, e.g. `alarm:4:red`. It evaporates
+/// everywhere but on sugar and the base, like a channel from
+/// `PheromoneChannelSettings::new`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelDeclaration {
+    pub name: String,
+    pub evaporation_rate: u32,
+    pub color: Color,
+}
+
+impl ChannelDeclaration {
+    #[must_use]
+    pub fn settings(&self) -> PheromoneChannelSettings {
+        let mut settings = PheromoneChannelSettings::new(&self.name);
+        settings.evaporation_rate = self.evaporation_rate;
+        settings.color = self.color;
+        settings
+    }
+}
+
+impl FromStr for ChannelDeclaration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid pheromone channel {s}, expected name:rate:color");
+        let mut parts = s.split(':');
+        let (Some(name), Some(rate), Some(color), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        if name.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            evaporation_rate: rate.parse().map_err(|_| invalid())?,
+            color: COLOR_NAMES
+                .iter()
+                .find(|(_, color_name)| *color_name == color)
+                .map(|(color, _)| *color)
+                .ok_or_else(invalid)?,
+        })
+    }
+}
+
+impl fmt::Display for ChannelDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let color = COLOR_NAMES
+            .iter()
+            .find(|(color, _)| *color == self.color)
+            .map_or("white", |(_, color_name)| color_name);
+        write!(f, "{}:{}:{color}", self.name, self.evaporation_rate)
+    }
+}
+
+/// Channels declared in the config, separated by spaces.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelDeclarations(pub Vec<ChannelDeclaration>);
+
+impl FromStr for ChannelDeclarations {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for ChannelDeclarations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, declaration) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{declaration}")?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct PheromoneChannels {
+    channels: Vec<PheromoneChannelSettings>,
+}
+
+impl Default for PheromoneChannels {
+    fn default() -> Self {
+        Self::from_config(&SimulationConfig::default())
+            .expect("the default config declares no channels")
+    }
+}
+
+impl PheromoneChannels {
+    /// The built-in channels, set up with the pheromone parameters of
+    /// `config`, followed by the channels it declares.
+    ///
+    /// # Errors
+    ///
+    /// If a declared channel can't be registered, see `check_registration`.
+    pub fn from_config(config: &SimulationConfig) -> Result<Self, String> {
+        let mut channels = Self::new();
+
+        let mut food = PheromoneChannelSettings::new("food");
+        food.evaporation_rate = config.evaporation_rate;
+        food.boosts.push((EntityType::Sugar, config.sugar_boost));
+        food.color = Color::Green;
+        channels.register(food)?;
+
+        let mut base = PheromoneChannelSettings::new("base");
+        base.evaporation_rate = config.evaporation_rate;
+        base.boosts.push((EntityType::Base, config.base_boost));
+        base.color = Color::Blue;
+        channels.register(base)?;
+
+        let mut repellent = PheromoneChannelSettings::new("repellent");
+        repellent.evaporation_rate = config.repellent_evaporation_rate;
+        // Dead ends next to the base or sugar shouldn't block them forever
+        repellent.evaporation_exempt_at = vec![];
+        repellent.color = Color::Magenta;
+        channels.register(repellent)?;
+
+        for declaration in &config.channels.0 {
+            channels.register(declaration.settings())?;
+        }
+
+        Ok(channels)
+    }
+
+    /// Channels without the default ones.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { channels: vec![] }
+    }
+
+    /// Why `name` can't be registered next, if it can't.
+    ///
+    /// # Errors
+    ///
+    /// If `name` is taken, or a built-in channel has to come first.
+    pub fn check_registration(&self, name: &str) -> Result<(), String> {
+        if self.find(name).is_some() {
+            return Err(format!("Pheromone channel {name} is already registered!"));
+        }
+
+        match PheromoneChannel::BUILTIN_NAMES.get(self.channels.len()) {
+            Some(builtin) if *builtin != name => Err(format!(
+                "Pheromone channel {builtin} has to be registered before {name}!"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds a channel and returns its handle.
+    ///
+    /// # Errors
+    ///
+    /// If `check_registration` fails for the name of the channel.
+    pub fn register(
+        &mut self,
+        settings: PheromoneChannelSettings,
+    ) -> Result<PheromoneChannel, String> {
+        self.check_registration(&settings.name)?;
+        self.channels.push(settings);
+        Ok(PheromoneChannel(self.channels.len() - 1))
+    }
+
+    #[must_use]
+    pub fn get(&self, channel: PheromoneChannel) -> &PheromoneChannelSettings {
+        &self.channels[channel.0]
+    }
+
+    pub fn find(&self, name: &str) -> Option<PheromoneChannel> {
+        self.channels
+            .iter()
+            .position(|settings| settings.name == name)
+            .map(PheromoneChannel)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (PheromoneChannel, &PheromoneChannelSettings)> {
+        self.channels
+            .iter()
+            .enumerate()
+            .map(|(i, settings)| (PheromoneChannel(i), settings))
+    }
+}
@@ -1,30 +1,24 @@
-use crate::entities::*;
+use crate::channels::PheromoneChannel;
+use crate::coordinate::Coordinate;
+use crate::entity_store::EntityIndex;
 use crate::entity_store::PheromoneGenerationNr;
-use crate::utils::*;
 use std::cmp::Ordering;
 use std::collections::{HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
 
-#[derive(Clone, Debug)]
+/// Position in fixed-point coordinates, see `Coordinate`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct PositionComponent {
-    pub x: f64,
-    pub y: f64,
+    pub x: Coordinate,
+    pub y: Coordinate,
 }
 
 impl Default for PositionComponent {
     fn default() -> Self {
-        Self { x: 0.5, y: 0.5 }
+        Self::new(0.5, 0.5)
     }
 }
 
-impl PartialEq for PositionComponent {
-    fn eq(&self, other: &Self) -> bool {
-        self.cmp(other) == Ordering::Equal
-    }
-}
-
-impl Eq for PositionComponent {}
-
 impl PartialOrd for PositionComponent {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -33,14 +27,45 @@ impl PartialOrd for PositionComponent {
 
 impl Ord for PositionComponent {
     fn cmp(&self, other: &Self) -> Ordering {
-        let ordering_y = cmp_float(self.y, other.y);
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
 
-        if ordering_y == Ordering::Equal {
-            cmp_float(self.x, other.x)
-        } else {
-            ordering_y
+impl PositionComponent {
+    /// The position closest to `x`, `y`.
+    #[must_use]
+    pub fn new(x: f64, y: f64) -> Self {
+        Self {
+            x: Coordinate::from_f64(x),
+            y: Coordinate::from_f64(y),
+        }
+    }
+
+    /// The position closest to `x`, `y` with `resolution` steps per
+    /// cell, see `Coordinate::rounded`.
+    #[must_use]
+    pub fn rounded(x: f64, y: f64, resolution: u32) -> Self {
+        Self {
+            x: Coordinate::rounded(x, resolution),
+            y: Coordinate::rounded(y, resolution),
         }
     }
+
+    #[must_use]
+    pub fn to_f64(&self) -> (f64, f64) {
+        (self.x.to_f64(), self.y.to_f64())
+    }
+
+    /// The position closest to this one moved by `dx`, `dy`.
+    #[must_use]
+    pub fn offset(&self, dx: f64, dy: f64) -> Self {
+        Self::new(self.x.to_f64() + dx, self.y.to_f64() + dy)
+    }
+
+    #[must_use]
+    pub fn distance(&self, other: &Self) -> f64 {
+        (self.x.to_f64() - other.x.to_f64()).hypot(self.y.to_f64() - other.y.to_f64())
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -77,18 +102,15 @@ impl Ord for CoarsePositionComponent {
 
 impl From<PositionComponent> for CoarsePositionComponent {
     fn from(pos: PositionComponent) -> Self {
-        Self {
-            x: pos.x.floor() as u64,
-            y: pos.y.floor() as u64,
-        }
+        Self::from(&pos)
     }
 }
 
 impl From<&PositionComponent> for CoarsePositionComponent {
     fn from(pos: &PositionComponent) -> Self {
         Self {
-            x: pos.x.floor() as u64,
-            y: pos.y.floor() as u64,
+            x: pos.x.cell(),
+            y: pos.y.cell(),
         }
     }
 }
@@ -100,20 +122,29 @@ impl Hash for CoarsePositionComponent {
     }
 }
 
-#[derive(PartialEq, Debug, Default)]
-pub struct EdibleComponent {}
-
 #[derive(PartialEq, Debug)]
-pub struct PheromoneTypeComponent {
-    pub ph_type: PheromoneType,
+pub struct EdibleComponent {
+    /// Weight of a single item taken from this source.
+    pub weight: u32,
+    /// Items left, or None if the source never runs out.
+    pub items: Option<u32>,
 }
 
-#[derive(PartialEq, Debug, Default)]
+impl Default for EdibleComponent {
+    fn default() -> Self {
+        Self {
+            weight: 1,
+            items: None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
 pub struct PheromoneGenerationComponent {
     pub generation: PheromoneGenerationNr,
 }
 
-#[derive(PartialEq, Debug, Default)]
+#[derive(Clone, PartialEq, Debug, Default)]
 pub struct IntensityComponent {
     pub strength: u32,
 }
@@ -121,7 +152,7 @@ pub struct IntensityComponent {
 #[derive(PartialEq, Debug)]
 pub struct ReleasingPheromoneComponent {
     pub ticks_left: u32,
-    pub ph_type: PheromoneType,
+    pub channel: PheromoneChannel,
 }
 
 #[derive(PartialEq, Clone, Debug, Default)]
@@ -131,7 +162,36 @@ pub struct DirectionComponent {
 }
 
 #[derive(PartialEq, Debug, Default)]
-pub struct CarryingFoodComponent {}
+pub struct CarryingFoodComponent {
+    /// Total weight of the carried items.
+    pub amount: u32,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct CapacityComponent {
+    /// Maximum weight an ant can carry.
+    pub capacity: u32,
+}
+
+impl Default for CapacityComponent {
+    fn default() -> Self {
+        Self { capacity: 1 }
+    }
+}
+
+impl CapacityComponent {
+    /// Fraction of a full step an ant makes with the given load. Every
+    /// unit of weight beyond the first slows the ant down relative to
+    /// its capacity.
+    #[must_use]
+    pub fn speed(&self, load: u32) -> f64 {
+        if load <= 1 {
+            1.0
+        } else {
+            f64::from(self.capacity) / f64::from(self.capacity + load - 1)
+        }
+    }
+}
 
 #[derive(PartialEq, Debug, Default)]
 pub struct BuilderComponent {}
@@ -139,23 +199,51 @@ pub struct BuilderComponent {}
 #[derive(PartialEq, Debug, Default)]
 pub struct ImpenetrableComponent {}
 
+#[derive(Default)]
 pub struct ShortMemory {
     pub pos: HashSet<CoarsePositionComponent>,
     pub pos_queue: VecDeque<CoarsePositionComponent>,
-    pub size: usize,
-}
-
-impl Default for ShortMemory {
-    fn default() -> Self {
-        Self {
-            pos: HashSet::new(),
-            pos_queue: VecDeque::new(),
-            size: 16,
-        }
-    }
 }
 
 #[derive(PartialEq, Debug, Default)]
 pub struct AdventurousComponent {
     pub ticks_left: u32,
 }
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Caste {
+    /// Explores without following or laying trails.
+    Scout,
+    /// Follows and lays trails.
+    Forager,
+    /// Waits at the base until recruited.
+    Reserve,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct CasteComponent {
+    pub caste: Caste,
+    /// Deliveries per `delivery_rate_window` ticks needed to recruit
+    /// this ant as a forager.
+    pub threshold: u32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TandemRole {
+    Leader,
+    Follower,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct TandemComponent {
+    pub partner: EntityIndex,
+    pub role: TandemRole,
+    /// Ticks in a row the follower has been too far behind.
+    pub waited: u32,
+}
+
+/// Position of a food source an ant has visited before.
+#[derive(PartialEq, Debug)]
+pub struct KnownFoodComponent {
+    pub pos: PositionComponent,
+}
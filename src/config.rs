@@ -0,0 +1,165 @@
+use crate::channels::{ChannelDeclarations, PheromoneChannels};
+use crate::coordinate::Coordinate;
+use std::f64::consts::PI;
+use std::fmt;
+use std::io::{self, BufRead};
+
+macro_rules! simulation_config {
+    ($($(#[$doc:meta])* $field:ident: $type:ty = $default:expr,)*) => {
+        /// Parameters steering the behaviour of the ants. The pheromone
+        /// parameters set up the built-in channels when a game is built,
+        /// see `PheromoneChannels::from_config`.
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct SimulationConfig {
+            $($(#[$doc])* pub $field: $type,)*
+        }
+
+        impl Default for SimulationConfig {
+            fn default() -> Self {
+                Self {
+                    $($field: $default,)*
+                }
+            }
+        }
+
+        impl SimulationConfig {
+            /// Every parameter by name, in the order they are written.
+            pub fn values(&self) -> Vec<(&'static str, String)> {
+                vec![$((stringify!($field), self.$field.to_string()),)*]
+            }
+
+            /// Sets the parameter called `key` from its written form, if
+            /// the result passes `check`.
+            ///
+            /// # Errors
+            ///
+            /// If `key` is unknown, `value` doesn't parse or the result
+            /// fails `check`. The config is left as it was.
+            pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+                let mut config = self.clone();
+                match key {
+                    $(stringify!($field) => {
+                        config.$field = value
+                            .parse()
+                            .map_err(|_| format!("Invalid value for {}: {}", key, value))?;
+                    })*
+                    _ => return Err(format!("Unknown parameter: {}", key)),
+                }
+
+                config.check()?;
+                *self = config;
+                Ok(())
+            }
+        }
+    };
+}
+
+simulation_config! {
+    /// Strength of a pheromone released by an ant, before boosts.
+    pheromone_strength: u32 = 16,
+    /// Strength of the repellent pheromone left at a dead end.
+    repellent_strength: u32 = 32,
+    /// Ticks an ant keeps releasing pheromones after leaving sugar or the base.
+    pheromone_ticks: u32 = 999,
+    /// Standard deviation of a random turn, in multiples of PI.
+    /// 99.7% of the turns are within 3 standard deviations.
+    turn_std_dev: f64 = 1.0 / 3.0,
+    /// Sharpest turn, in radians, an ant makes to follow a trail.
+    max_trail_turn: f64 = PI / 1.8,
+    /// Random directions an ant tries before turning around.
+    direction_attempts: u32 = 8,
+    /// Number of recently visited cells an ant avoids.
+    memory_size: usize = 16,
+    /// Distance at which a tandem leader stops to wait for its follower.
+    tandem_max_distance: f64 = 2.0,
+    /// Ticks a tandem leader waits for its follower before giving up.
+    tandem_max_wait: u32 = 20,
+    /// Number of ticks over which the delivery rate is measured.
+    delivery_rate_window: u32 = 50,
+    /// Strength food and base pheromones lose every tick.
+    evaporation_rate: u32 = 1,
+    /// Strength the repellent pheromone loses every tick.
+    repellent_evaporation_rate: u32 = 2,
+    /// Multiplier for food pheromones released on sugar.
+    sugar_boost: u32 = 10,
+    /// Multiplier for base pheromones released on the base.
+    base_boost: u32 = 10,
+    /// Steps per cell that ants move in. Has to divide
+    /// `Coordinate::SCALE`.
+    position_resolution: u32 = 100,
+    /// Channels registered after the built-in ones, see
+    /// `ChannelDeclaration`.
+    channels: ChannelDeclarations = ChannelDeclarations::default(),
+}
+
+impl SimulationConfig {
+    /// Why the parameters can't be simulated, if they can't.
+    ///
+    /// # Errors
+    ///
+    /// With the first parameter that is out of range, or a declared
+    /// channel that can't be registered.
+    pub fn check(&self) -> Result<(), String> {
+        if self.pheromone_ticks == 0 {
+            return Err("pheromone_ticks has to be at least 1".to_owned());
+        }
+        if self.memory_size == 0 {
+            return Err("memory_size has to be at least 1".to_owned());
+        }
+        if !self.turn_std_dev.is_finite() || self.turn_std_dev <= 0.0 {
+            return Err("turn_std_dev has to be positive".to_owned());
+        }
+        if self.direction_attempts == 0 {
+            return Err("direction_attempts has to be at least 1".to_owned());
+        }
+        if self.delivery_rate_window == 0 {
+            return Err("delivery_rate_window has to be at least 1".to_owned());
+        }
+        if !Coordinate::SCALE.is_multiple_of(self.position_resolution) {
+            return Err(format!(
+                "position_resolution has to divide {}",
+                Coordinate::SCALE
+            ));
+        }
+        PheromoneChannels::from_config(self)?;
+
+        Ok(())
+    }
+
+    /// Reads `key = value` lines. Parameters that aren't mentioned keep
+    /// their default, lines starting with `#` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// If reading fails, or with `InvalidData` for a line that isn't a
+    /// valid parameter.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut config = Self::default();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("Invalid line: {line}")))?;
+            config.set(key.trim(), value.trim()).map_err(invalid)?;
+        }
+
+        Ok(config)
+    }
+}
+
+impl fmt::Display for SimulationConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, value) in self.values() {
+            writeln!(f, "{key} = {value}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+
+/// A coordinate in `SCALE` steps per cell. Unlike f64 it is totally
+/// ordered and hashable, so positions compare exactly.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Coordinate(i32);
+
+impl Coordinate {
+    /// Steps per cell coordinates are stored in. Ants move in coarser
+    /// steps, see `SimulationConfig::position_resolution`.
+    pub const SCALE: u32 = 10_000;
+
+    /// The coordinate closest to `value`.
+    #[must_use]
+    pub fn from_f64(value: f64) -> Self {
+        Self::rounded(value, Self::SCALE)
+    }
+
+    /// The coordinate closest to `value` with `resolution` steps per
+    /// cell, which has to divide `SCALE`.
+    #[must_use]
+    pub fn rounded(value: f64, resolution: u32) -> Self {
+        Self::scaled((value * f64::from(resolution)).round(), resolution)
+    }
+
+    /// The closest coordinate to `value` on the side of `from`, with
+    /// `resolution` steps per cell.
+    #[must_use]
+    pub fn towards(value: f64, from: f64, resolution: u32) -> Self {
+        let steps = value * f64::from(resolution);
+        if value > from {
+            Self::scaled(steps.floor(), resolution)
+        } else {
+            Self::scaled(steps.ceil(), resolution)
+        }
+    }
+
+    fn scaled(steps: f64, resolution: u32) -> Self {
+        Self(steps as i32 * (Self::SCALE / resolution) as i32)
+    }
+
+    #[must_use]
+    pub fn to_f64(self) -> f64 {
+        f64::from(self.0) / f64::from(Self::SCALE)
+    }
+
+    /// The coordinate moved into `0..size` by wrapping around.
+    #[must_use]
+    pub fn wrap(self, size: f64) -> Self {
+        Self(
+            self.0
+                .rem_euclid((size * f64::from(Self::SCALE)).round() as i32),
+        )
+    }
+
+    /// Index of the cell the coordinate is on. Cells left of or above
+    /// the world are folded onto the first one.
+    #[must_use]
+    pub fn cell(self) -> u64 {
+        self.0.div_euclid(Self::SCALE as i32).max(0) as u64
+    }
+}
+
+impl fmt::Display for Coordinate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_f64())
+    }
+}
+
+#[cfg(test)]
+mod coordinate_tests {
+    use super::*;
+
+    #[test]
+    fn test_from_f64() {
+        assert_eq!(Coordinate::from_f64(1.234_56), Coordinate(12_346));
+        assert_eq!(Coordinate::from_f64(-0.000_04), Coordinate(0));
+        assert!((Coordinate::from_f64(2.5).to_f64() - 2.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_rounded() {
+        assert_eq!(Coordinate::rounded(1.234, 100), Coordinate(12_300));
+        assert_eq!(Coordinate::rounded(1.235, 100), Coordinate(12_400));
+        assert_eq!(Coordinate::rounded(-0.004, 100), Coordinate(0));
+        assert_eq!(Coordinate::rounded(-1.236, 100), Coordinate(-12_400));
+        assert_eq!(Coordinate::rounded(1.3, 4), Coordinate(12_500));
+    }
+
+    #[test]
+    fn test_towards() {
+        assert_eq!(Coordinate::towards(1.239, 1.0, 100), Coordinate(12_300));
+        assert_eq!(Coordinate::towards(1.231, 2.0, 100), Coordinate(12_400));
+        assert_eq!(Coordinate::towards(-0.001, 0.0, 100), Coordinate(0));
+        assert_eq!(Coordinate::towards(-0.009, -1.0, 100), Coordinate(-100));
+        assert_eq!(Coordinate::towards(1.9, 1.0, 2), Coordinate(15_000));
+    }
+
+    #[test]
+    fn test_cell() {
+        assert_eq!(Coordinate::from_f64(2.99).cell(), 2);
+        assert_eq!(Coordinate::from_f64(3.0).cell(), 3);
+        assert_eq!(Coordinate::from_f64(-0.5).cell(), 0);
+        assert_eq!(Coordinate::from_f64(-1.5).cell(), 0);
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(Coordinate::from_f64(-0.5).wrap(5.0), Coordinate(45_000));
+        assert_eq!(Coordinate::from_f64(5.25).wrap(5.0), Coordinate(2_500));
+        assert_eq!(Coordinate::from_f64(5.0).wrap(5.0), Coordinate(0));
+        assert_eq!(Coordinate::from_f64(-10.5).wrap(5.0), Coordinate(45_000));
+    }
+}
@@ -1,198 +1,487 @@
-use crate::components::*;
-use crate::entities::*;
-use std::collections::{BTreeMap, HashSet};
+use crate::channels::{PheromoneChannel, PheromoneChannels};
+use crate::components::{
+    AdventurousComponent, BuilderComponent, CapacityComponent, CarryingFoodComponent,
+    CasteComponent, CoarsePositionComponent, DirectionComponent, EdibleComponent,
+    ImpenetrableComponent, KnownFoodComponent, PositionComponent, ReleasingPheromoneComponent,
+    ShortMemory, TandemComponent,
+};
+use crate::entities::{AntEntity, BaseEntity, EntityType, SugarEntity, WallEntity};
+use crate::events::{Event, Events};
+use crate::fields::{PheromoneCell, PheromoneField};
+use crate::spatial::SpatialIndex;
+use crate::storage::{DenseStorage, SparseStorage};
+use crate::validation::Violation;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 
-pub type EntityIndex = usize;
 pub type PheromoneGenerationNr = u32;
 
+/// Handle to an entity. Slots of destroyed entities are reused, the
+/// generation tells a handle to the current occupant of a slot apart
+/// from a stale handle to a destroyed one.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct EntityIndex {
+    index: usize,
+    generation: u32,
+}
+
+impl EntityIndex {
+    #[must_use]
+    pub fn index(self) -> usize {
+        self.index
+    }
+
+    #[must_use]
+    pub fn generation(self) -> u32 {
+        self.generation
+    }
+}
+
+/// Parses the `index.generation` form handles are saved in.
+impl FromStr for EntityIndex {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, generation) = s
+            .split_once('.')
+            .ok_or_else(|| format!("Invalid entity {s}"))?;
+
+        Ok(Self {
+            index: index.parse().map_err(|_| format!("Invalid entity {s}"))?,
+            generation: generation
+                .parse()
+                .map_err(|_| format!("Invalid entity {s}"))?,
+        })
+    }
+}
+
+impl fmt::Display for EntityIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.index)
+    }
+}
+
+/// Cells pheromones don't evaporate on, per channel, and the entity
+/// types they were collected for.
+struct EvaporationExempt {
+    entity_types: Vec<Vec<EntityType>>,
+    cells: Vec<HashSet<CoarsePositionComponent>>,
+}
+
+// Marker components and entity types have no data but are stored
+// like any other component
+#[allow(clippy::zero_sized_map_values)]
 #[derive(Default)]
 pub struct EntityStore {
-    pub new_index: EntityIndex,
+    // Current generation of every slot, and slots free for reuse
+    generations: Vec<u32>,
+    free_slots: Vec<usize>,
     pub pheromone_generation: PheromoneGenerationNr,
+    /// Ticks the game has run.
+    pub tick: u32,
 
-    pub entity_types: BTreeMap<EntityIndex, EntityType>,
+    pub entity_types: DenseStorage<EntityType>,
 
     // Entities
-    pub ants: BTreeMap<EntityIndex, AntEntity>,
-    pub pheromones: BTreeMap<EntityIndex, PheromoneEntity>,
-    pub sugars: BTreeMap<EntityIndex, SugarEntity>,
-    pub bases: BTreeMap<EntityIndex, BaseEntity>,
-    pub walls: BTreeMap<EntityIndex, WallEntity>,
+    pub ants: SparseStorage<AntEntity>,
+    pub sugars: SparseStorage<SugarEntity>,
+    pub bases: SparseStorage<BaseEntity>,
+    pub walls: SparseStorage<WallEntity>,
 
     // Components
-    positions: BTreeMap<EntityIndex, PositionComponent>,
-    positions_lookup: BTreeMap<CoarsePositionComponent, HashSet<EntityIndex>>,
-    directions: BTreeMap<EntityIndex, DirectionComponent>,
-    pub edibles: BTreeMap<EntityIndex, EdibleComponent>,
-    pub releasing_pheromones: BTreeMap<EntityIndex, ReleasingPheromoneComponent>,
-    pub pheromone_generations: BTreeMap<EntityIndex, PheromoneGenerationComponent>,
-    pub intensities: BTreeMap<EntityIndex, IntensityComponent>,
-    pub pheromone_types: BTreeMap<EntityIndex, PheromoneType>,
-    pub carrying_food: BTreeMap<EntityIndex, CarryingFoodComponent>,
-    pub builders: BTreeMap<EntityIndex, BuilderComponent>,
-    pub impenetrables: BTreeMap<EntityIndex, ImpenetrableComponent>,
-    pub memories: BTreeMap<EntityIndex, ShortMemory>,
-    pub adventurous: BTreeMap<EntityIndex, AdventurousComponent>,
+    positions: DenseStorage<PositionComponent>,
+    positions_lookup: SpatialIndex,
+    directions: DenseStorage<DirectionComponent>,
+    pub edibles: SparseStorage<EdibleComponent>,
+    pub releasing_pheromones: DenseStorage<ReleasingPheromoneComponent>,
+    pub carrying_food: SparseStorage<CarryingFoodComponent>,
+    pub capacities: DenseStorage<CapacityComponent>,
+    pub builders: SparseStorage<BuilderComponent>,
+    pub impenetrables: SparseStorage<ImpenetrableComponent>,
+    pub memories: DenseStorage<ShortMemory>,
+    pub adventurous: SparseStorage<AdventurousComponent>,
+    pub castes: SparseStorage<CasteComponent>,
+    pub tandems: SparseStorage<TandemComponent>,
+    pub known_food: SparseStorage<KnownFoodComponent>,
 
     // Misc
     pub food_in_base: u32,
+    pub recent_deliveries: VecDeque<u32>,
+    pub pheromone_channels: PheromoneChannels,
+    pub pheromone_fields: Vec<PheromoneField>,
+    // Collected again after an entity of an exempt type changed cells
+    evaporation_exempt: Option<EvaporationExempt>,
+    pub events: Events,
 }
 
 impl EntityStore {
+    /// Store for a world of `width` by `height` cells, positions outside
+    /// of it can't be occupied.
+    #[must_use]
+    pub fn bounded(width: f64, height: f64) -> Self {
+        Self {
+            positions_lookup: SpatialIndex::grid(width.ceil() as usize, height.ceil() as usize),
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn is_bounded(&self) -> bool {
+        matches!(self.positions_lookup, SpatialIndex::Grid { .. })
+    }
+
+    /// Generation of every slot and the slots free for reuse.
+    #[must_use]
+    pub(crate) fn slots(&self) -> (&[u32], &[usize]) {
+        (&self.generations, &self.free_slots)
+    }
+
+    pub(crate) fn restore_slots(&mut self, generations: Vec<u32>, free_slots: Vec<usize>) {
+        self.generations = generations;
+        self.free_slots = free_slots;
+    }
+
+    /// Adds an entity with a handle from a save, without any of the
+    /// components `create_entity` adds.
+    pub(crate) fn restore_entity(&mut self, id: EntityIndex, entity_type: EntityType) {
+        match entity_type {
+            EntityType::Ant => {
+                self.ants.insert(id, AntEntity {});
+            }
+            EntityType::Sugar => {
+                self.sugars.insert(id, SugarEntity {});
+            }
+            EntityType::Base => {
+                self.bases.insert(id, BaseEntity {});
+            }
+            EntityType::Wall => {
+                self.walls.insert(id, WallEntity {});
+            }
+        }
+        self.entity_types.insert(id, entity_type);
+    }
+
+    pub(crate) fn restore_position(
+        &mut self,
+        id: EntityIndex,
+        pos: &PositionComponent,
+        direction: Option<DirectionComponent>,
+    ) {
+        self.update_position(id, pos);
+        if let Some(direction) = direction {
+            self.directions.insert(id, direction);
+        }
+    }
+
     fn get_new_index(&mut self) -> EntityIndex {
-        self.new_index += 1;
-        self.new_index - 1
+        if let Some(index) = self.free_slots.pop() {
+            EntityIndex {
+                index,
+                generation: self.generations[index],
+            }
+        } else {
+            self.generations.push(0);
+            EntityIndex {
+                index: self.generations.len() - 1,
+                generation: 0,
+            }
+        }
+    }
+
+    /// Whether `id` refers to an entity that hasn't been destroyed.
+    #[must_use]
+    pub fn is_alive(&self, id: EntityIndex) -> bool {
+        self.generations.get(id.index) == Some(&id.generation)
+            && self.entity_types.contains_key(&id)
     }
 
+    #[must_use]
     pub fn get_position(&self, id: EntityIndex) -> Option<&PositionComponent> {
         self.positions.get(&id)
     }
 
+    #[must_use]
     pub fn get_direction(&self, id: EntityIndex) -> Option<&DirectionComponent> {
         self.directions.get(&id)
     }
 
-    pub fn get_entities_at(&self, search_pos: &PositionComponent) -> Option<&HashSet<EntityIndex>> {
+    /// Overrides the direction `update_position` derived from the last move.
+    pub fn set_direction(&mut self, id: EntityIndex, direction: DirectionComponent) {
+        self.directions.insert(id, direction);
+    }
+
+    #[must_use]
+    pub fn get_entities_at(&self, search_pos: &PositionComponent) -> Option<&[EntityIndex]> {
         self.positions_lookup
             .get(&CoarsePositionComponent::from(search_pos))
     }
 
+    /// Non-empty cells between the cells of `min` and `max`, inclusive.
+    pub fn get_cells_in_rect(
+        &self,
+        min: &PositionComponent,
+        max: &PositionComponent,
+    ) -> impl Iterator<Item = (CoarsePositionComponent, &[EntityIndex])> {
+        self.positions_lookup.cells_in_rect(
+            &CoarsePositionComponent::from(min),
+            &CoarsePositionComponent::from(max),
+        )
+    }
+
+    /// Entities of `entity_type` on the cell of `search_pos`.
     pub fn get_entities_with_type_at(
         &self,
         search_pos: &PositionComponent,
         entity_type: EntityType,
-    ) -> Option<HashSet<EntityIndex>> {
-        if let Some(ids) = self.get_entities_at(search_pos) {
-            let mut results = HashSet::new();
-            for id in ids {
-                if self.entity_types.get(&id).unwrap() == &entity_type {
-                    results.insert(*id);
-                }
-            }
+    ) -> impl Iterator<Item = EntityIndex> + '_ {
+        self.get_entities_at(search_pos)
+            .unwrap_or_default()
+            .iter()
+            .copied()
+            .filter(move |id| self.matches_type(*id, Some(entity_type)))
+    }
 
-            if results.is_empty() {
-                None
-            } else {
-                Some(results)
-            }
-        } else {
-            None
+    fn matches_type(&self, id: EntityIndex, entity_type: Option<EntityType>) -> bool {
+        entity_type.is_none_or(|entity_type| self.entity_types.get(&id) == Some(&entity_type))
+    }
+
+    /// Entities on the cells between the cells of `min` and `max`,
+    /// cell by cell, row by row.
+    #[must_use]
+    pub fn get_entities_in_rect(
+        &self,
+        min: &PositionComponent,
+        max: &PositionComponent,
+        entity_type: Option<EntityType>,
+    ) -> Vec<EntityIndex> {
+        let mut results = vec![];
+        for (_, ids) in self.get_cells_in_rect(min, max) {
+            let start = results.len();
+            results.extend(ids.iter().filter(|id| self.matches_type(**id, entity_type)));
+            results[start..].sort_unstable();
         }
+
+        results
     }
 
+    /// Entities at most `radius` away from `pos`, closest first.
+    ///
+    /// # Panics
+    ///
+    /// If an entity in the grid has no position, which `validate` reports.
+    #[must_use]
+    pub fn get_entities_within(
+        &self,
+        pos: &PositionComponent,
+        radius: f64,
+        entity_type: Option<EntityType>,
+    ) -> Vec<(EntityIndex, f64)> {
+        let min = pos.offset(-radius, -radius);
+        let max = pos.offset(radius, radius);
+
+        let mut results: Vec<(EntityIndex, f64)> = self
+            .get_cells_in_rect(&min, &max)
+            .flat_map(|(_, ids)| ids.iter())
+            .filter(|id| self.matches_type(**id, entity_type))
+            .map(|id| (*id, self.positions.get(id).unwrap().distance(pos)))
+            .filter(|(_, distance)| *distance <= radius)
+            .collect();
+        results.sort_unstable_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        results
+    }
+
+    /// Whether `id` shares its cell with an entity of `entity_type`.
+    #[must_use]
+    pub fn is_at(&self, id: EntityIndex, entity_type: EntityType) -> bool {
+        self.get_position(id).is_some_and(|pos| {
+            self.get_entities_with_type_at(pos, entity_type)
+                .next()
+                .is_some()
+        })
+    }
+
+    #[must_use]
     pub fn pos_is_impenetrable(&self, pos: &PositionComponent) -> bool {
         if let Some(entities) = self.get_entities_at(pos) {
             entities
                 .iter()
-                .any(|id| self.impenetrables.get(&id).is_some())
+                .any(|id| self.impenetrables.contains_key(id))
         } else {
             false
         }
     }
 
+    #[must_use]
     pub fn get_pheromone_with_type_at(
         &self,
         search_pos: &PositionComponent,
-        pheromone_type: PheromoneType,
-    ) -> Option<EntityIndex> {
-        if let Some(ph_ids) = self.get_entities_with_type_at(&search_pos, EntityType::Pheromone) {
-            let ph_id: Vec<EntityIndex> = ph_ids
-                .into_iter()
-                .filter(|id| self.pheromone_types.get(id).unwrap() == &pheromone_type)
+        channel: PheromoneChannel,
+    ) -> Option<&PheromoneCell> {
+        self.pheromone_fields
+            .get(channel.index())
+            .and_then(|field| field.get(&CoarsePositionComponent::from(search_pos)))
+    }
+
+    pub fn pheromone_field_mut(&mut self, channel: PheromoneChannel) -> &mut PheromoneField {
+        if self.pheromone_fields.len() <= channel.index() {
+            self.pheromone_fields
+                .resize_with(channel.index() + 1, PheromoneField::default);
+        }
+
+        &mut self.pheromone_fields[channel.index()]
+    }
+
+    /// Lowers the pheromones of `channel` by its evaporation rate, except
+    /// on cells with an entity type the channel is exempt at. Returns the
+    /// cells whose pheromone evaporated completely.
+    ///
+    /// # Panics
+    ///
+    /// If `channel` isn't registered.
+    pub fn evaporate(&mut self, channel: PheromoneChannel) -> Vec<CoarsePositionComponent> {
+        let up_to_date = self.evaporation_exempt.as_ref().is_some_and(|exempt| {
+            exempt.entity_types.iter().eq(self
+                .pheromone_channels
+                .iter()
+                .map(|(_, settings)| &settings.evaporation_exempt_at))
+        });
+        if !up_to_date {
+            let entity_types: Vec<Vec<EntityType>> = self
+                .pheromone_channels
+                .iter()
+                .map(|(_, settings)| settings.evaporation_exempt_at.clone())
+                .collect();
+            let cells = entity_types
+                .iter()
+                .map(|exempt_at| {
+                    self.entity_types
+                        .iter()
+                        .filter(|(_, entity_type)| exempt_at.contains(entity_type))
+                        .filter_map(|(id, _)| self.get_position(*id))
+                        .map(CoarsePositionComponent::from)
+                        .collect()
+                })
                 .collect();
+            self.evaporation_exempt = Some(EvaporationExempt {
+                entity_types,
+                cells,
+            });
+        }
 
-            if ph_id.is_empty() {
-                None
-            } else if ph_id.len() > 1 {
-                panic!("More than one food pheromone in the same position!");
-            } else {
-                Some(ph_id[0])
-            }
-        } else {
-            None
+        let rate = self.pheromone_channels.get(channel).evaporation_rate;
+        self.pheromone_field_mut(channel);
+        let exempt = &self.evaporation_exempt.as_ref().unwrap().cells[channel.index()];
+        self.pheromone_fields[channel.index()].evaporate(rate, exempt)
+    }
+
+    fn exempt_cell_changed(&mut self, id: EntityIndex) {
+        let exempt = self.entity_types.get(&id).is_none_or(|entity_type| {
+            self.pheromone_channels
+                .iter()
+                .any(|(_, settings)| settings.evaporation_exempt_at.contains(entity_type))
+        });
+        if exempt {
+            self.evaporation_exempt = None;
         }
     }
 
+    /// Whether `pos` is inside a bounded world, always true otherwise.
+    #[must_use]
+    pub fn is_in_world(&self, pos: &PositionComponent) -> bool {
+        let (x, y) = pos.to_f64();
+        !self.is_bounded()
+            || (x >= 0.0 && y >= 0.0 && self.cell_is_in_world(&CoarsePositionComponent::from(pos)))
+    }
+
+    #[must_use]
+    pub fn cell_is_in_world(&self, cell: &CoarsePositionComponent) -> bool {
+        self.positions_lookup.covers(cell)
+    }
+
+    /// Panics if `new_pos` is outside of a bounded world, see `is_in_world`.
     pub fn update_position(&mut self, id: EntityIndex, new_pos: &PositionComponent) {
+        let new_cell = CoarsePositionComponent::from(new_pos);
         let old_pos = self.positions.get(&id);
+        let mut moved_cell = true;
         if let Some(old_pos) = old_pos {
             self.directions.insert(
                 id,
                 DirectionComponent {
-                    x: new_pos.x - old_pos.x,
-                    y: new_pos.y - old_pos.y,
+                    x: new_pos.x.to_f64() - old_pos.x.to_f64(),
+                    y: new_pos.y.to_f64() - old_pos.y.to_f64(),
                 },
             );
 
-            if let Some(entities) = self
-                .positions_lookup
-                .get_mut(&CoarsePositionComponent::from(old_pos))
-            {
-                entities.remove(&id);
-
-                if entities.is_empty() {
-                    self.positions_lookup
-                        .remove(&CoarsePositionComponent::from(old_pos));
-                }
+            let old_cell = CoarsePositionComponent::from(old_pos);
+            moved_cell = old_cell != new_cell;
+            if moved_cell {
+                self.positions_lookup.remove(&old_cell, id);
             }
         }
 
         self.positions.insert(id, new_pos.clone());
 
-        if self
-            .positions_lookup
-            .get(&CoarsePositionComponent::from(new_pos))
-            .is_none()
-        {
-            self.positions_lookup
-                .insert(CoarsePositionComponent::from(new_pos), HashSet::new());
+        if moved_cell {
+            self.positions_lookup.insert(new_cell, id);
+            self.exempt_cell_changed(id);
         }
-
-        self.positions_lookup
-            .get_mut(&CoarsePositionComponent::from(new_pos))
-            .unwrap()
-            .insert(id);
     }
 
     pub fn remove_position(&mut self, id: EntityIndex) {
         if let Some(pos) = self.get_position(id) {
-            let cloned_pos = pos.clone();
-            let entities = self
-                .positions_lookup
-                .get_mut(&CoarsePositionComponent::from(&cloned_pos))
-                .unwrap();
-            entities.remove(&id);
-
-            if entities.is_empty() {
-                self.positions_lookup
-                    .remove(&CoarsePositionComponent::from(&cloned_pos));
-            }
+            let cell = CoarsePositionComponent::from(pos);
+            self.positions_lookup.remove(&cell, id);
+            self.exempt_cell_changed(id);
         }
 
         self.positions.remove(&id);
         self.directions.remove(&id);
     }
 
-    pub fn add_to_short_memory(&mut self, ant_id: EntityIndex, pos: &PositionComponent) {
+    /// Remembers `pos`, forgetting the oldest cell beyond `size`.
+    ///
+    /// # Panics
+    ///
+    /// If `ant_id` isn't an ant.
+    pub fn add_to_short_memory(
+        &mut self,
+        ant_id: EntityIndex,
+        pos: &PositionComponent,
+        size: usize,
+    ) {
         let memory = self.memories.get_mut(&ant_id).unwrap();
         let coarse_pos = CoarsePositionComponent::from(pos);
 
-        if memory.pos_queue.len() >= memory.size {
+        // Also shrinks the memory when `size` was lowered
+        while !memory.pos_queue.is_empty() && memory.pos_queue.len() >= size {
+            // A cell that was visited again after the forgotten visit
+            // is still in the queue, so it stays remembered
             let removed = memory.pos_queue.pop_front().unwrap();
-            memory.pos.remove(&removed);
+            if !memory.pos_queue.contains(&removed) {
+                memory.pos.remove(&removed);
+            }
         }
 
         memory.pos_queue.push_back(coarse_pos.clone());
         memory.pos.insert(coarse_pos);
     }
 
+    /// # Panics
+    ///
+    /// If `ant_id` isn't an ant.
+    #[must_use]
     pub fn in_short_memory(&self, ant_id: EntityIndex, pos: &PositionComponent) -> bool {
         let memory = self.memories.get(&ant_id).unwrap();
         let coarse_pos = CoarsePositionComponent::from(pos);
 
-        memory.pos.get(&coarse_pos).is_some()
+        memory.pos.contains(&coarse_pos)
     }
 
+    /// # Panics
+    ///
+    /// If `ant_id` isn't an ant.
     pub fn clear_memory(&mut self, ant_id: EntityIndex) {
         let memory = self.memories.get_mut(&ant_id).unwrap();
         memory.pos_queue.clear();
@@ -204,13 +493,10 @@ impl EntityStore {
         match entity_type {
             EntityType::Ant => {
                 self.update_position(index, &PositionComponent::default());
-                self.memories.insert(index, ShortMemory::default());
+                self.add_component(index, ShortMemory::default());
+                self.add_component(index, CapacityComponent::default());
                 self.ants.insert(index, AntEntity {});
             }
-            EntityType::Pheromone => {
-                self.update_position(index, &PositionComponent::default());
-                self.pheromones.insert(index, PheromoneEntity {});
-            }
             EntityType::Sugar => {
                 self.update_position(index, &PositionComponent::default());
                 self.edibles.insert(index, EdibleComponent::default());
@@ -228,7 +514,197 @@ impl EntityStore {
             }
         }
         self.entity_types.insert(index, entity_type);
+        self.events.emit(Event::EntityCreated {
+            id: index,
+            entity_type,
+        });
 
         index
     }
+
+    /// Removes `id` and all its components. Does nothing if it's
+    /// already gone.
+    ///
+    /// # Panics
+    ///
+    /// If `id` has no entity type, which `validate` reports.
+    pub fn destroy_entity(&mut self, id: EntityIndex) {
+        if !self.is_alive(id) {
+            return;
+        }
+
+        self.generations[id.index] += 1;
+        self.free_slots.push(id.index);
+        self.remove_position(id);
+
+        let entity_type = self.entity_types.remove(&id).unwrap();
+        match entity_type {
+            EntityType::Ant => {
+                self.ants.remove(&id);
+            }
+            EntityType::Sugar => {
+                self.sugars.remove(&id);
+            }
+            EntityType::Base => {
+                self.bases.remove(&id);
+            }
+            EntityType::Wall => {
+                self.walls.remove(&id);
+            }
+        }
+
+        self.remove_component::<EdibleComponent>(id);
+        self.remove_component::<ReleasingPheromoneComponent>(id);
+        self.remove_component::<CarryingFoodComponent>(id);
+        self.remove_component::<CapacityComponent>(id);
+        self.remove_component::<BuilderComponent>(id);
+        self.remove_component::<ImpenetrableComponent>(id);
+        self.remove_component::<ShortMemory>(id);
+        self.remove_component::<AdventurousComponent>(id);
+        self.remove_component::<CasteComponent>(id);
+        self.remove_component::<KnownFoodComponent>(id);
+
+        // A tandem run ends when either ant is gone
+        if let Some(tandem) = self.tandems.remove(&id) {
+            self.tandems.remove(&tandem.partner);
+        }
+
+        self.events.emit(Event::EntityDestroyed { id, entity_type });
+
+        debug_assert!(
+            !self.is_referenced(id),
+            "Entity {} is still referenced after it was destroyed!",
+            id
+        );
+    }
+
+    /// Checks the bookkeeping of positions and entity types, and that
+    /// edibles have a weight. Pheromones are kept per cell, so they
+    /// can't overlap.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = vec![];
+
+        for (id, pos) in self.positions.iter() {
+            let cell = CoarsePositionComponent::from(pos);
+            let listed = self
+                .positions_lookup
+                .get(&cell)
+                .is_some_and(|ids| ids.contains(id));
+            if !listed {
+                violations.push(Violation::MissingFromLookup { id: *id, cell });
+            }
+        }
+
+        for (cell, ids) in self.positions_lookup.cells() {
+            for (i, id) in ids.iter().enumerate() {
+                if ids[..i].contains(id) {
+                    violations.push(Violation::DuplicateInLookup {
+                        id: *id,
+                        cell: cell.clone(),
+                    });
+                } else if self.positions.get(id).map(CoarsePositionComponent::from)
+                    != Some(cell.clone())
+                {
+                    violations.push(Violation::StaleInLookup {
+                        id: *id,
+                        cell: cell.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut ids: BTreeSet<EntityIndex> = self.entity_types.keys().copied().collect();
+        ids.extend(self.ants.keys());
+        ids.extend(self.sugars.keys());
+        ids.extend(self.bases.keys());
+        ids.extend(self.walls.keys());
+        for id in ids {
+            if self.generations.get(id.index) != Some(&id.generation) {
+                violations.push(Violation::StaleHandle { id });
+            }
+
+            let maps: Vec<EntityType> = [
+                (EntityType::Ant, self.ants.contains_key(&id)),
+                (EntityType::Sugar, self.sugars.contains_key(&id)),
+                (EntityType::Base, self.bases.contains_key(&id)),
+                (EntityType::Wall, self.walls.contains_key(&id)),
+            ]
+            .iter()
+            .filter(|(_, contains)| *contains)
+            .map(|(entity_type, _)| *entity_type)
+            .collect();
+            let entity_type = self.entity_types.get(&id).copied();
+            if maps.as_slice() != entity_type.as_slice() {
+                violations.push(Violation::WrongEntityMaps {
+                    id,
+                    entity_type,
+                    maps,
+                });
+            }
+        }
+
+        let referenced: BTreeSet<EntityIndex> = self
+            .positions
+            .keys()
+            .chain(self.directions.keys())
+            .chain(self.edibles.keys())
+            .chain(self.releasing_pheromones.keys())
+            .chain(self.carrying_food.keys())
+            .chain(self.capacities.keys())
+            .chain(self.builders.keys())
+            .chain(self.impenetrables.keys())
+            .chain(self.memories.keys())
+            .chain(self.adventurous.keys())
+            .chain(self.castes.keys())
+            .chain(self.tandems.keys())
+            .chain(self.tandems.values().map(|tandem| &tandem.partner))
+            .chain(self.known_food.keys())
+            .copied()
+            .collect();
+        for id in referenced {
+            if !self.is_alive(id) {
+                violations.push(Violation::MissingEntity { id });
+            }
+        }
+
+        let free_slots: HashSet<usize> = self.free_slots.iter().copied().collect();
+        for id in self.entity_types.keys() {
+            if free_slots.contains(&id.index) {
+                violations.push(Violation::FreeSlotInUse { id: *id });
+            }
+        }
+
+        for (id, edible) in &self.edibles {
+            if edible.weight == 0 {
+                violations.push(Violation::WeightlessEdible { id: *id });
+            }
+        }
+
+        violations
+    }
+
+    /// Whether any entity map, component map or the position lookup
+    /// still mentions `id`.
+    fn is_referenced(&self, id: EntityIndex) -> bool {
+        self.entity_types.contains_key(&id)
+            || self.ants.contains_key(&id)
+            || self.sugars.contains_key(&id)
+            || self.bases.contains_key(&id)
+            || self.walls.contains_key(&id)
+            || self.positions.contains_key(&id)
+            || self.positions_lookup.contains(id)
+            || self.directions.contains_key(&id)
+            || self.edibles.contains_key(&id)
+            || self.releasing_pheromones.contains_key(&id)
+            || self.carrying_food.contains_key(&id)
+            || self.capacities.contains_key(&id)
+            || self.builders.contains_key(&id)
+            || self.impenetrables.contains_key(&id)
+            || self.memories.contains_key(&id)
+            || self.adventurous.contains_key(&id)
+            || self.castes.contains_key(&id)
+            || self.tandems.contains_key(&id)
+            || self.tandems.values().any(|tandem| tandem.partner == id)
+            || self.known_food.contains_key(&id)
+    }
 }
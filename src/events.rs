@@ -0,0 +1,80 @@
+use crate::channels::PheromoneChannel;
+use crate::components::{CoarsePositionComponent, PositionComponent};
+use crate::entities::EntityType;
+use crate::entity_store::EntityIndex;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    FoodPickedUp {
+        ant: EntityIndex,
+        amount: u32,
+    },
+    FoodDelivered {
+        ant: EntityIndex,
+        amount: u32,
+    },
+    PheromoneCreated {
+        pos: CoarsePositionComponent,
+        channel: PheromoneChannel,
+        strength: u32,
+    },
+    /// A pheromone was released on a cell that already had one.
+    PheromoneMerged {
+        pos: CoarsePositionComponent,
+        channel: PheromoneChannel,
+        strength: u32,
+    },
+    PheromoneExpired {
+        pos: CoarsePositionComponent,
+        channel: PheromoneChannel,
+    },
+    /// An ant that should have moved stayed where it was.
+    AntBlocked {
+        ant: EntityIndex,
+    },
+    /// An ant ran into a dead end and turned around.
+    AntReversed {
+        ant: EntityIndex,
+        pos: PositionComponent,
+    },
+    EntityCreated {
+        id: EntityIndex,
+        entity_type: EntityType,
+    },
+    EntityDestroyed {
+        id: EntityIndex,
+        entity_type: EntityType,
+    },
+}
+
+type Subscriber = Box<dyn FnMut(&Event) + Send + Sync>;
+
+/// Events of the current tick. Subscribers see every event as it
+/// happens, the rest can drain them after the tick.
+#[derive(Default)]
+pub struct Events {
+    queue: Vec<Event>,
+    subscribers: Vec<Subscriber>,
+}
+
+impl Events {
+    pub fn emit(&mut self, event: Event) {
+        for subscriber in &mut self.subscribers {
+            subscriber(&event);
+        }
+
+        self.queue.push(event);
+    }
+
+    pub fn subscribe<F: FnMut(&Event) + Send + Sync + 'static>(&mut self, subscriber: F) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Event> + '_ {
+        self.queue.drain(..)
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
@@ -0,0 +1,105 @@
+use crate::components::{
+    CoarsePositionComponent, IntensityComponent, PheromoneGenerationComponent,
+};
+use crate::entity_store::PheromoneGenerationNr;
+use std::collections::HashSet;
+
+/// The pheromone of one channel on one cell.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PheromoneCell {
+    pub intensity: IntensityComponent,
+    pub generation: PheromoneGenerationComponent,
+}
+
+/// Pheromones of one channel, stored densely by coarse cell. Grows to
+/// fit the cells pheromones are released on.
+#[derive(Default)]
+pub struct PheromoneField {
+    rows: Vec<Vec<PheromoneCell>>,
+}
+
+impl PheromoneField {
+    /// The pheromone on `pos`, or None if the cell has no pheromone.
+    #[must_use]
+    pub fn get(&self, pos: &CoarsePositionComponent) -> Option<&PheromoneCell> {
+        self.rows
+            .get(pos.y as usize)
+            .and_then(|row| row.get(pos.x as usize))
+            .filter(|cell| cell.intensity.strength > 0)
+    }
+
+    /// Adds `strength` to the pheromone on `pos`. A cell that already
+    /// has a pheromone keeps its generation, this allows ants to
+    /// reinforce existing pheromones.
+    pub fn add(
+        &mut self,
+        pos: &CoarsePositionComponent,
+        strength: u32,
+        generation: PheromoneGenerationNr,
+    ) -> &PheromoneCell {
+        let (x, y) = (pos.x as usize, pos.y as usize);
+        if self.rows.len() <= y {
+            self.rows.resize_with(y + 1, Vec::new);
+        }
+
+        let row = &mut self.rows[y];
+        if row.len() <= x {
+            row.resize_with(x + 1, PheromoneCell::default);
+        }
+
+        let cell = &mut row[x];
+        if cell.intensity.strength == 0 {
+            cell.generation.generation = generation;
+        }
+        cell.intensity.strength += strength;
+
+        cell
+    }
+
+    /// Cells that have a pheromone, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = (CoarsePositionComponent, &PheromoneCell)> {
+        self.rows.iter().enumerate().flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|(_, cell)| cell.intensity.strength > 0)
+                .map(move |(x, cell)| {
+                    (
+                        CoarsePositionComponent {
+                            x: x as u64,
+                            y: y as u64,
+                        },
+                        cell,
+                    )
+                })
+        })
+    }
+
+    /// Lowers every pheromone by `rate`, except the ones on `exempt`.
+    /// Returns the cells whose pheromone evaporated completely.
+    pub fn evaporate(
+        &mut self,
+        rate: u32,
+        exempt: &HashSet<CoarsePositionComponent>,
+    ) -> Vec<CoarsePositionComponent> {
+        let mut expired = vec![];
+        for (y, row) in self.rows.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                let pos = CoarsePositionComponent {
+                    x: x as u64,
+                    y: y as u64,
+                };
+                if cell.intensity.strength == 0 || exempt.contains(&pos) {
+                    continue;
+                }
+
+                cell.intensity.strength = cell.intensity.strength.saturating_sub(rate);
+
+                if cell.intensity.strength == 0 {
+                    expired.push(pos);
+                }
+            }
+        }
+
+        expired
+    }
+}
@@ -1,48 +1,131 @@
-use crate::components::*;
-use crate::entities::*;
-use crate::entity_store::*;
-use crate::rand::Rng;
-use colored::*;
+use crate::channels::PheromoneChannel;
+use crate::components::{
+    AdventurousComponent, BuilderComponent, CapacityComponent, CarryingFoodComponent, Caste,
+    CasteComponent, CoarsePositionComponent, DirectionComponent, IntensityComponent,
+    KnownFoodComponent, PositionComponent, ReleasingPheromoneComponent, TandemComponent,
+    TandemRole,
+};
+use crate::config::SimulationConfig;
+use crate::coordinate::Coordinate;
+use crate::entities::{AntEntity, EntityType};
+use crate::entity_store::{EntityIndex, EntityStore};
+use crate::events::Event;
+use crate::fields::PheromoneCell;
+use crate::history::History;
+use crate::system::{Scheduler, System};
+use colored::Colorize;
 use rand::prelude::SeedableRng;
+use rand::Rng;
 use rand_distr::{Distribution, Normal};
-use std::cell::RefCell;
+use std::collections::HashSet;
 use std::f64::consts::PI;
-use std::fmt;
+use std::fmt::{self, Write as _};
 
-thread_local!(static RNG: RefCell<rand::rngs::StdRng> = RefCell::new(SeedableRng::from_seed([0; 32])));
+macro_rules! builtin_system {
+    ($system:ident, $name:expr, $method:ident) => {
+        struct $system;
+
+        impl System for $system {
+            fn name(&self) -> &str {
+                $name
+            }
+
+            fn run(&mut self, game: &mut Game) {
+                game.$method();
+            }
+        }
+    };
+}
+
+/// What happens at the edges of the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Topology {
+    /// Ants can't leave the world.
+    Bounded,
+    /// Ants leaving on one side come back on the other.
+    Torus,
+}
+
+builtin_system!(PheromoneSystem, "pheromones", pheromones);
+builtin_system!(AntSystem, "ants", ants);
+builtin_system!(TandemRunSystem, "tandem_runs", tandem_runs);
+builtin_system!(CasteSystem, "castes", castes);
 
-// TODO is this a System?
 pub struct Game {
     width: f64,
     height: f64,
     pub entity_store: EntityStore,
+    pub scheduler: Scheduler,
+    seed: u64,
+    /// When set, ants decide where to move on this many threads. Every
+    /// ant draws from its own RNG stream derived from `seed`, so results
+    /// don't depend on the number of threads.
+    pub movement_threads: Option<usize>,
+    pub topology: Topology,
+    pub config: SimulationConfig,
+    history: Option<History>,
 }
 
-impl Game {
-    pub fn init(entity_store: EntityStore, width: f64, height: f64) -> Self {
-        Self {
-            width,
-            height,
-            entity_store,
+type AntPositions = Vec<(EntityIndex, PositionComponent)>;
+
+/// splitmix64, so nearby inputs give unrelated outputs.
+fn splitmix64(z: u64) -> u64 {
+    let mut z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Everything ants look at to decide where to move. Movement only reads
+/// the game, so the view is shared with the movement threads.
+struct Movement<'a> {
+    entity_store: &'a EntityStore,
+    config: &'a SimulationConfig,
+    width: f64,
+    height: f64,
+    topology: Topology,
+    seed: u64,
+}
+
+impl Movement<'_> {
+    fn pos_is_in_bounds(&self, pos: &PositionComponent) -> bool {
+        let (x, y) = pos.to_f64();
+        x >= 0.0 && y >= 0.0 && x < self.width && y < self.height
+    }
+
+    /// `pos` moved back into the world if it left it on a torus.
+    fn wrap(&self, pos: &PositionComponent) -> PositionComponent {
+        match self.topology {
+            Topology::Bounded => pos.clone(),
+            Topology::Torus => PositionComponent {
+                x: pos.x.wrap(self.width),
+                y: pos.y.wrap(self.height),
+            },
         }
     }
 
-    fn pos_is_in_bounds(&self, pos: &PositionComponent) -> bool {
-        pos.x >= 0.0 && pos.y >= 0.0 && pos.x < self.width && pos.y < self.height
+    /// `pos` moved by `dx`, `dy`, in the steps ants move in.
+    fn offset(&self, pos: &PositionComponent, dx: f64, dy: f64) -> PositionComponent {
+        let (x, y) = pos.to_f64();
+        PositionComponent::rounded(x + dx, y + dy, self.config.position_resolution)
     }
 
     fn pos_can_be_occupied(&self, pos: &PositionComponent) -> bool {
-        if self.pos_is_in_bounds(pos) {
-            !self.entity_store.pos_is_impenetrable(pos)
+        let pos = self.wrap(pos);
+        if self.pos_is_in_bounds(&pos) {
+            !self.entity_store.pos_is_impenetrable(&pos)
         } else {
             false
         }
     }
 
-    fn calc_random_direction(&self, direction: &DirectionComponent) -> DirectionComponent {
-        let std_dev = 1.0 / 3.0; // 99.7% is within 3x std dev
+    fn calc_random_direction<R: Rng>(
+        direction: &DirectionComponent,
+        std_dev: f64,
+        rng: &mut R,
+    ) -> DirectionComponent {
         let normal = Normal::new(0.0, std_dev).unwrap();
-        let mut r = RNG.with(|rng| normal.sample(&mut *rng.borrow_mut()));
+        let mut r = normal.sample(rng);
 
         r *= PI; // [-pi, pi], centered around pi
         r += direction.y.atan2(direction.x);
@@ -53,11 +136,12 @@ impl Game {
         }
     }
 
-    fn dirs_to_strongest_adjecent_pheromones(
+    /// The cells around `pos` an ant can step on, with the direction
+    /// to each of them.
+    fn adjacent_cells(
         &self,
         pos: &PositionComponent,
-        ph_type: PheromoneType,
-    ) -> Option<Vec<DirectionComponent>> {
+    ) -> impl Iterator<Item = (PositionComponent, DirectionComponent)> + '_ {
         let mut directions = vec![(1, 0), (-1, 0), (0, 1), (0, -1)];
         let diagonals = [1, -1];
         for i in &diagonals {
@@ -66,28 +150,67 @@ impl Game {
             }
         }
 
-        let positions = directions
-            .iter()
-            .map(|d| PositionComponent {
-                x: pos.x + f64::from(d.0),
-                y: pos.y + f64::from(d.1),
+        let pos = pos.clone();
+        directions
+            .into_iter()
+            .map(move |d| {
+                let dir = DirectionComponent {
+                    x: f64::from(d.0),
+                    y: f64::from(d.1),
+                };
+                (self.wrap(&pos.offset(dir.x, dir.y)), dir)
             })
-            .filter(|p| self.pos_can_be_occupied(&p));
+            .filter(move |(p, _)| self.pos_can_be_occupied(p))
+    }
+
+    /// Direction to sugar next to `pos`, or to the base if the ant
+    /// carries food.
+    fn dir_to_adjacent_goal(
+        &self,
+        ant_id: EntityIndex,
+        pos: &PositionComponent,
+    ) -> Option<DirectionComponent> {
+        let goal = if self.entity_store.carrying_food.contains_key(&ant_id) {
+            EntityType::Base
+        } else {
+            EntityType::Sugar
+        };
 
+        self.adjacent_cells(pos)
+            .find(|(new_pos, _)| {
+                self.entity_store
+                    .get_entities_with_type_at(new_pos, goal)
+                    .next()
+                    .is_some()
+            })
+            .map(|(_, dir)| dir)
+    }
+
+    fn dirs_to_strongest_adjecent_pheromones(
+        &self,
+        pos: &PositionComponent,
+        channel: PheromoneChannel,
+        min_strength: u32,
+    ) -> Option<Vec<DirectionComponent>> {
         let mut strength_to_dir = vec![];
-        for new_pos in positions {
-            if let Some(ph_id) = self
+        for (new_pos, dir) in self.adjacent_cells(pos) {
+            if let Some(cell) = self
                 .entity_store
-                .get_pheromone_with_type_at(&new_pos, ph_type)
+                .get_pheromone_with_type_at(&new_pos, channel)
             {
-                let intensity = self.entity_store.intensities.get(&ph_id).unwrap();
-                strength_to_dir.push((
-                    intensity.strength,
-                    DirectionComponent {
-                        x: new_pos.x - pos.x,
-                        y: new_pos.y - pos.y,
-                    },
-                ));
+                let intensity = &cell.intensity;
+                let repellent_strength = self
+                    .entity_store
+                    .get_pheromone_with_type_at(&new_pos, PheromoneChannel::REPELLENT)
+                    .map_or(0, |cell| cell.intensity.strength);
+
+                // Trails that lead into a dead end are avoided until
+                // the repellent has evaporated below the trail strength.
+                if repellent_strength >= intensity.strength || intensity.strength < min_strength {
+                    continue;
+                }
+
+                strength_to_dir.push((intensity.strength, dir));
             }
         }
 
@@ -105,24 +228,28 @@ impl Game {
         ant_id: EntityIndex,
         pos: &PositionComponent,
         direction: &DirectionComponent,
-        ph_type: PheromoneType,
+        channel: PheromoneChannel,
         allow_sharp_turns: bool,
     ) -> Option<DirectionComponent> {
-        if let Some(dirs) = self.dirs_to_strongest_adjecent_pheromones(pos, ph_type) {
+        // Foragers only follow trails that were reinforced by more
+        // than a single ant.
+        let min_strength = if self.caste(ant_id) == Some(Caste::Forager) {
+            self.config.pheromone_strength + 1
+        } else {
+            0
+        };
+
+        if let Some(dirs) = self.dirs_to_strongest_adjecent_pheromones(pos, channel, min_strength) {
             for dir in dirs {
                 let new_angle = dir.y.atan2(dir.x).abs();
                 let current_angle = direction.y.atan2(direction.x).abs();
                 let angle_diff = (new_angle - current_angle).abs();
 
-                // only allow 90° (PI / 2) turns or less
-                if !allow_sharp_turns && angle_diff > PI / 1.8 {
+                if !allow_sharp_turns && angle_diff > self.config.max_trail_turn {
                     continue;
                 }
 
-                let new_pos = PositionComponent {
-                    x: pos.x + dir.x,
-                    y: pos.y + dir.y,
-                };
+                let new_pos = self.wrap(&pos.offset(dir.x, dir.y));
 
                 if !self.entity_store.in_short_memory(ant_id, &new_pos) {
                     return Some(dir);
@@ -133,299 +260,693 @@ impl Game {
         None
     }
 
-    fn get_new_ant_direction(
+    fn get_new_ant_direction<R: Rng>(
         &self,
         ant_id: EntityIndex,
         pos: &PositionComponent,
         direction: &DirectionComponent,
-    ) -> DirectionComponent {
+        rng: &mut R,
+    ) -> (DirectionComponent, bool) {
         let mut direction = direction.clone();
-        let is_adventurous = self.entity_store.adventurous.get(&ant_id).is_some();
+        let is_adventurous = self.entity_store.adventurous.contains_key(&ant_id);
         assert!(!is_adventurous);
         let allow_sharp_turns = self
             .entity_store
             .get_entities_with_type_at(pos, EntityType::Sugar)
+            .next()
             .is_some()
             || self
                 .entity_store
                 .get_entities_with_type_at(pos, EntityType::Base)
+                .next()
                 .is_some();
 
-        if self.entity_store.carrying_food.get(&ant_id).is_none() {
+        // Ants don't walk past what they are looking for, even if that
+        // cell is next to a dead end
+        if let Some(dir) = self.dir_to_adjacent_goal(ant_id, pos) {
+            return (dir, false);
+        }
+
+        let follows_trails = self.caste(ant_id) != Some(Caste::Scout);
+
+        if follows_trails && !self.entity_store.carrying_food.contains_key(&ant_id) {
             if let Some(dir) = self.dir_to_strongest_adjecent_pheromone(
                 ant_id,
                 pos,
                 &direction,
-                PheromoneType::Food,
+                PheromoneChannel::FOOD,
                 allow_sharp_turns,
             ) {
-                return dir;
+                return (dir, false);
             }
         }
 
-        if self.entity_store.carrying_food.get(&ant_id).is_some() {
+        if follows_trails && self.entity_store.carrying_food.contains_key(&ant_id) {
             if let Some(dir) = self.dir_to_strongest_adjecent_pheromone(
                 ant_id,
                 pos,
                 &direction,
-                PheromoneType::Base,
+                PheromoneChannel::BASE,
                 allow_sharp_turns,
             ) {
-                return dir;
+                return (dir, false);
             }
         }
 
-        let mut dir = self.calc_random_direction(&direction);
-        let mut new_pos = PositionComponent {
-            x: pos.x + dir.x,
-            y: pos.y + dir.y,
+        // Recently visited cells and dead ends are avoided until the
+        // ant runs out of tries and turns around.
+        let avoids = |new_pos: &PositionComponent| {
+            let new_pos = self.wrap(new_pos);
+            self.entity_store.in_short_memory(ant_id, &new_pos)
+                || self
+                    .entity_store
+                    .get_pheromone_with_type_at(&new_pos, PheromoneChannel::REPELLENT)
+                    .is_some()
         };
+
+        let mut dir = Self::calc_random_direction(&direction, self.config.turn_std_dev, rng);
+        let mut new_pos = self.offset(pos, dir.x, dir.y);
         let mut tries = 1;
+        let mut dead_end = false;
         while !self.pos_can_be_occupied(&new_pos)
-            || (self.entity_store.in_short_memory(ant_id, &new_pos) && tries < 8)
+            || (avoids(&new_pos) && tries < self.config.direction_attempts)
         {
-            if tries == 8 {
+            if tries == self.config.direction_attempts {
                 direction.x = -direction.x;
                 direction.y = -direction.y;
+                dead_end = true;
             }
-            dir = self.calc_random_direction(&direction);
-            new_pos = PositionComponent {
-                x: pos.x + dir.x,
-                y: pos.y + dir.y,
-            };
+            dir = Self::calc_random_direction(&direction, self.config.turn_std_dev, rng);
+            new_pos = self.offset(pos, dir.x, dir.y);
             tries += 1;
         }
 
-        dir
+        (dir, dead_end)
+    }
+
+    fn next_ant_pos<R: Rng>(
+        &self,
+        ant_id: EntityIndex,
+        pos: &PositionComponent,
+        rng: &mut R,
+    ) -> (PositionComponent, bool) {
+        let direction = self
+            .entity_store
+            .get_direction(ant_id)
+            .unwrap_or(&DirectionComponent { x: 1.0, y: 0.0 });
+        let (direction, dead_end) = self.get_new_ant_direction(ant_id, pos, direction, rng);
+        let speed = self.ant_speed(ant_id);
+        let (x, y) = pos.to_f64();
+        let (new_x, new_y) = (x + direction.x * speed, y + direction.y * speed);
+
+        // The closest position, but towards the old position if that
+        // would end up on a cell that can't be occupied. Slowed ants
+        // can still be blocked there, they stay put then.
+        let resolution = self.config.position_resolution;
+        let closest = PositionComponent::rounded(new_x, new_y, resolution);
+        let towards_old = PositionComponent {
+            x: Coordinate::towards(new_x, x, resolution),
+            y: Coordinate::towards(new_y, y, resolution),
+        };
+        let new_pos = if self.pos_can_be_occupied(&closest) {
+            closest
+        } else if self.pos_can_be_occupied(&towards_old) {
+            towards_old
+        } else {
+            pos.clone()
+        };
+
+        (new_pos, dead_end)
+    }
+
+    /// Walks straight to `target`, or returns None if the way is blocked.
+    fn step_towards(
+        &self,
+        ant_id: EntityIndex,
+        pos: &PositionComponent,
+        target: &PositionComponent,
+    ) -> Option<PositionComponent> {
+        let distance = pos.distance(target);
+        if distance == 0.0 {
+            return Some(pos.clone());
+        }
+
+        let step = distance.min(self.ant_speed(ant_id)) / distance;
+        let (x, y) = pos.to_f64();
+        let (target_x, target_y) = target.to_f64();
+        let new_pos = self.offset(pos, (target_x - x) * step, (target_y - y) * step);
+
+        if self.pos_can_be_occupied(&new_pos) {
+            Some(new_pos)
+        } else {
+            None
+        }
+    }
+
+    fn tandem_step<R: Rng>(
+        &self,
+        leader_id: EntityIndex,
+        follower_id: EntityIndex,
+        rng: &mut R,
+    ) -> (PositionComponent, PositionComponent) {
+        let leader_pos = self.entity_store.get_position(leader_id).unwrap();
+        let follower_pos = self.entity_store.get_position(follower_id).unwrap();
+        let food_pos = &self.entity_store.known_food.get(&leader_id).unwrap().pos;
+
+        let new_leader_pos = if leader_pos.distance(follower_pos) > self.config.tandem_max_distance
+        {
+            leader_pos.clone()
+        } else {
+            self.step_towards(leader_id, leader_pos, food_pos)
+                .unwrap_or_else(|| self.next_ant_pos(leader_id, leader_pos, rng).0)
+        };
+        let new_follower_pos = self
+            .step_towards(follower_id, follower_pos, &new_leader_pos)
+            .unwrap_or_else(|| self.next_ant_pos(follower_id, follower_pos, rng).0);
+
+        (new_leader_pos, new_follower_pos)
+    }
+
+    /// RNG stream of a single ant for the current tick.
+    fn ant_rng(&self, ant_id: EntityIndex) -> rand::rngs::StdRng {
+        // Mixed in one at a time, so no other seed, tick and ant give
+        // the same stream
+        let z = splitmix64(splitmix64(self.seed) ^ u64::from(self.entity_store.tick));
+        SeedableRng::seed_from_u64(splitmix64(z ^ ant_id.index() as u64))
+    }
+
+    /// Decides where `ant_id` moves to.
+    fn move_ant<R: Rng>(
+        &self,
+        ant_id: EntityIndex,
+        rng: &mut R,
+        new_positions: &mut Vec<(EntityIndex, PositionComponent)>,
+        dead_ends: &mut Vec<(EntityIndex, PositionComponent)>,
+    ) {
+        let pos = self.entity_store.get_position(ant_id).unwrap();
+
+        if let Some(tandem) = self.entity_store.tandems.get(&ant_id) {
+            // Followers move together with their leader
+            if tandem.role == TandemRole::Leader {
+                let (leader_pos, follower_pos) = self.tandem_step(ant_id, tandem.partner, rng);
+                new_positions.push((ant_id, leader_pos));
+                new_positions.push((tandem.partner, follower_pos));
+            }
+        } else if self.entity_store.builders.contains_key(&ant_id)
+            || self.caste(ant_id) == Some(Caste::Reserve)
+        {
+            new_positions.push((ant_id, pos.clone()));
+        } else {
+            let (new_pos, dead_end) = self.next_ant_pos(ant_id, pos, rng);
+            if dead_end {
+                dead_ends.push((ant_id, pos.clone()));
+            }
+
+            new_positions.push((ant_id, new_pos));
+        }
+    }
+
+    fn ant_speed(&self, ant_id: EntityIndex) -> f64 {
+        let load = self
+            .entity_store
+            .carrying_food
+            .get(&ant_id)
+            .map_or(0, |carrying| carrying.amount);
+        self.entity_store
+            .get::<CapacityComponent>(ant_id)
+            .unwrap()
+            .speed(load)
+    }
+
+    fn caste(&self, ant_id: EntityIndex) -> Option<Caste> {
+        self.entity_store.castes.get(&ant_id).map(|c| c.caste)
+    }
+
+    /// New positions of `ant_ids` and the ants among them that had to
+    /// turn around, in the order of `ant_ids`.
+    fn move_ants(&self, ant_ids: &[EntityIndex]) -> (AntPositions, AntPositions) {
+        let mut new_positions = vec![];
+        let mut dead_ends = vec![];
+        for ant_id in ant_ids {
+            let mut rng = self.ant_rng(*ant_id);
+            self.move_ant(*ant_id, &mut rng, &mut new_positions, &mut dead_ends);
+        }
+
+        (new_positions, dead_ends)
+    }
+}
+
+impl Game {
+    #[must_use]
+    pub fn init(
+        entity_store: EntityStore,
+        width: f64,
+        height: f64,
+        config: SimulationConfig,
+    ) -> Self {
+        let mut scheduler = Scheduler::default();
+        scheduler.add(Box::new(PheromoneSystem));
+        scheduler.add(Box::new(AntSystem));
+
+        // Pairs have to be formed before ants move, and castes are
+        // switched based on where ants ended up.
+        scheduler.add_before("ants", Box::new(TandemRunSystem));
+        scheduler.add_after("ants", Box::new(CasteSystem));
+        scheduler.set_enabled("tandem_runs", false);
+
+        Self {
+            width,
+            height,
+            entity_store,
+            scheduler,
+            seed: 0,
+            movement_threads: None,
+            topology: Topology::Bounded,
+            config,
+            history: None,
+        }
+    }
+
+    #[must_use]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the random numbers of the game from `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn movement(&self) -> Movement<'_> {
+        Movement {
+            entity_store: &self.entity_store,
+            config: &self.config,
+            width: self.width,
+            height: self.height,
+            topology: self.topology,
+            seed: self.seed,
+        }
+    }
+
+    fn wrap(&self, pos: &PositionComponent) -> PositionComponent {
+        self.movement().wrap(pos)
+    }
+
+    fn caste(&self, ant_id: EntityIndex) -> Option<Caste> {
+        self.movement().caste(ant_id)
+    }
+
+    #[must_use]
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    #[must_use]
+    pub fn height(&self) -> f64 {
+        self.height
     }
 
     fn handle_new_ant_pos(&mut self, ant_id: EntityIndex, new_pos: &PositionComponent) {
-        let carrying_food = self.entity_store.carrying_food.get(&ant_id).is_some();
+        let carrying_food = self.entity_store.carrying_food.contains_key(&ant_id);
         let is_base = self
             .entity_store
-            .get_entities_with_type_at(&new_pos, EntityType::Base)
+            .get_entities_with_type_at(new_pos, EntityType::Base)
+            .next()
             .is_some();
-        let is_food = self
+        let food_source = self
             .entity_store
-            .get_entities_with_type_at(&new_pos, EntityType::Sugar)
-            .is_some();
+            .get_entities_with_type_at(new_pos, EntityType::Sugar)
+            .min();
 
-        if carrying_food {
+        let lays_trails = match self.caste(ant_id) {
+            Some(Caste::Scout | Caste::Reserve) => false,
+            Some(Caste::Forager) | None => true,
+        };
+
+        if !lays_trails {
+            self.entity_store.releasing_pheromones.remove(&ant_id);
+        } else if carrying_food {
             self.entity_store.releasing_pheromones.insert(
                 ant_id,
                 ReleasingPheromoneComponent {
-                    ph_type: PheromoneType::Base,
-                    ticks_left: 999, // todo
+                    channel: PheromoneChannel::BASE,
+                    ticks_left: self.config.pheromone_ticks,
                 },
             );
         } else {
             self.entity_store.releasing_pheromones.insert(
                 ant_id,
                 ReleasingPheromoneComponent {
-                    ph_type: PheromoneType::Food,
-                    ticks_left: 999, // todo
+                    channel: PheromoneChannel::FOOD,
+                    ticks_left: self.config.pheromone_ticks,
                 },
             );
         }
 
         if carrying_food && is_base {
-            println!("ant {} delivered food!", ant_id);
-            self.entity_store.food_in_base += 1;
-            self.entity_store.carrying_food.remove(&ant_id);
+            let load = self.entity_store.carrying_food.remove(&ant_id).unwrap();
+            self.entity_store.events.emit(Event::FoodDelivered {
+                ant: ant_id,
+                amount: load.amount,
+            });
+            self.entity_store.food_in_base += load.amount;
+            self.entity_store
+                .recent_deliveries
+                .push_back(self.entity_store.tick);
             self.entity_store.clear_memory(ant_id);
         }
 
-        if !carrying_food && is_food {
+        // Take as many items as the ant can carry
+        let amount = food_source.map_or(0, |sugar_id| {
+            let edible = self.entity_store.edibles.get(&sugar_id).unwrap();
+            let capacity = self.entity_store.capacities.get(&ant_id).unwrap().capacity;
+            let items = edible.items.map_or(capacity / edible.weight, |items| {
+                items.min(capacity / edible.weight)
+            });
+            items * edible.weight
+        });
+
+        if !carrying_food && amount > 0 {
+            let sugar_id = food_source.unwrap();
+            let edible = self.entity_store.edibles.get_mut(&sugar_id).unwrap();
+            let mut depleted = false;
+            if let Some(items) = &mut edible.items {
+                *items -= amount / edible.weight;
+                depleted = *items == 0;
+            }
+
             self.entity_store
                 .carrying_food
-                .insert(ant_id, CarryingFoodComponent {});
+                .insert(ant_id, CarryingFoodComponent { amount });
+            self.entity_store.events.emit(Event::FoodPickedUp {
+                ant: ant_id,
+                amount,
+            });
+            if depleted {
+                self.entity_store.destroy_entity(sugar_id);
+                self.forget_food_at(new_pos);
+            } else {
+                self.entity_store.known_food.insert(
+                    ant_id,
+                    KnownFoodComponent {
+                        pos: new_pos.clone(),
+                    },
+                );
+            }
             self.entity_store.clear_memory(ant_id);
-        }
 
-        self.entity_store.add_to_short_memory(ant_id, new_pos);
-    }
+            // A scout that finds food is recruited to bring it home
+            if let Some(caste) = self.entity_store.get_mut::<CasteComponent>(ant_id) {
+                if caste.caste == Caste::Scout {
+                    caste.caste = Caste::Forager;
+                }
+            }
+        }
 
-    fn remove_pheromone(&mut self, ph_id: EntityIndex) {
-        self.entity_store.remove_position(ph_id);
-        self.entity_store.intensities.remove(&ph_id);
-        self.entity_store.pheromone_types.remove(&ph_id);
-        self.entity_store.pheromone_generations.remove(&ph_id);
-        self.entity_store.pheromones.remove(&ph_id);
+        self.entity_store
+            .add_to_short_memory(ant_id, new_pos, self.config.memory_size);
     }
 
-    fn merge_and_clear_pheromones(
-        &mut self,
-        pos: &PositionComponent,
-        ph_type: PheromoneType,
-        extra_strength: u32,
-    ) -> (IntensityComponent, PheromoneGenerationComponent) {
-        let mut intensity = IntensityComponent {
-            strength: extra_strength,
-        };
-
-        // When merging pheromones the oldest generation should be
-        // kept. This allows ants to reinforce existing pheromones.
-        let mut generation = PheromoneGenerationComponent {
-            generation: self.entity_store.pheromone_generation,
-        };
-        let mut pheromones_to_delete = vec![];
-
-        if let Some(pheromones) = self
+    /// Makes ants forget the depleted food source on `pos`, ending the
+    /// tandem runs that lead there.
+    fn forget_food_at(&mut self, pos: &PositionComponent) {
+        let cell = CoarsePositionComponent::from(pos);
+        let ant_ids: Vec<EntityIndex> = self
             .entity_store
-            .get_entities_with_type_at(&pos, EntityType::Pheromone)
-        {
-            let pheromones: Vec<&EntityIndex> = pheromones
-                .iter()
-                .filter(|id| self.entity_store.pheromone_types.get(id).unwrap() == &ph_type)
-                .collect();
-
-            if !pheromones.is_empty() {
-                let merged_strength: u32 = pheromones
-                    .iter()
-                    .map(|p| self.entity_store.intensities.get(p).unwrap().strength)
-                    .sum();
-                intensity.strength += merged_strength;
-
-                generation.generation = pheromones
-                    .iter()
-                    .map(|p| {
-                        self.entity_store
-                            .pheromone_generations
-                            .get(p)
-                            .unwrap()
-                            .generation
-                    })
-                    .min()
-                    .unwrap_or(self.entity_store.pheromone_generation);
-
-                pheromones_to_delete = pheromones.iter().map(|p| **p).collect();
+            .known_food
+            .iter()
+            .filter(|(_, known_food)| CoarsePositionComponent::from(&known_food.pos) == cell)
+            .map(|(ant_id, _)| *ant_id)
+            .collect();
+
+        for ant_id in ant_ids {
+            self.entity_store.known_food.remove(&ant_id);
+            if let Some(tandem) = self.entity_store.tandems.remove(&ant_id) {
+                self.entity_store.tandems.remove(&tandem.partner);
             }
         }
-
-        for ph in pheromones_to_delete {
-            self.remove_pheromone(ph);
-        }
-
-        (intensity, generation)
     }
 
     fn increase_pheromone_strength_at(
         &mut self,
         pos: &PositionComponent,
-        ph_type: PheromoneType,
+        channel: PheromoneChannel,
         intensity: &IntensityComponent,
-    ) -> EntityIndex {
-        let (intensity, generation) =
-            self.merge_and_clear_pheromones(&pos, ph_type, intensity.strength);
-        let ph_id = self.entity_store.create_entity(EntityType::Pheromone);
-        self.entity_store.update_position(ph_id, &pos);
-        self.entity_store.intensities.insert(ph_id, intensity);
-        self.entity_store.pheromone_types.insert(ph_id, ph_type);
-        self.entity_store
-            .pheromone_generations
-            .insert(ph_id, generation);
-
-        ph_id
+    ) {
+        let generation = self.entity_store.pheromone_generation;
+        let cell = CoarsePositionComponent::from(pos);
+        let field = self.entity_store.pheromone_field_mut(channel);
+        let existed = field.get(&cell).is_some();
+        let strength = field
+            .add(&cell, intensity.strength, generation)
+            .intensity
+            .strength;
+
+        self.entity_store.events.emit(if existed {
+            Event::PheromoneMerged {
+                pos: cell,
+                channel,
+                strength,
+            }
+        } else {
+            Event::PheromoneCreated {
+                pos: cell,
+                channel,
+                strength,
+            }
+        });
     }
 
     fn release_pheromones(&mut self, ant_id: EntityIndex) {
-        const NEW_PHEROMONE_STRENGTH: u32 = 16;
-
         if let Some(releasing_pheromone_comp) =
             self.entity_store.releasing_pheromones.get_mut(&ant_id)
         {
-            releasing_pheromone_comp.ticks_left -= 1;
+            releasing_pheromone_comp.ticks_left =
+                releasing_pheromone_comp.ticks_left.saturating_sub(1);
 
             if releasing_pheromone_comp.ticks_left == 0 {
                 self.entity_store.releasing_pheromones.remove(&ant_id);
             } else {
-                match releasing_pheromone_comp.ph_type {
-                    PheromoneType::Food => {
-                        let ant_pos = self.entity_store.get_position(ant_id).unwrap().clone();
-                        let strength = if self
-                            .entity_store
-                            .get_entities_with_type_at(&ant_pos, EntityType::Sugar)
-                            .is_some()
-                        {
-                            NEW_PHEROMONE_STRENGTH * 10
-                        } else {
-                            NEW_PHEROMONE_STRENGTH
-                        };
-
-                        self.increase_pheromone_strength_at(
-                            &ant_pos,
-                            PheromoneType::Food,
-                            &IntensityComponent { strength },
-                        );
-                    }
-                    PheromoneType::Base => {
-                        let ant_pos = self.entity_store.get_position(ant_id).unwrap().clone();
-                        let strength = if self
-                            .entity_store
-                            .get_entities_with_type_at(&ant_pos, EntityType::Base)
-                            .is_some()
-                        {
-                            NEW_PHEROMONE_STRENGTH * 10
-                        } else {
-                            NEW_PHEROMONE_STRENGTH
-                        };
-                        self.increase_pheromone_strength_at(
-                            &ant_pos,
-                            PheromoneType::Base,
-                            &IntensityComponent { strength },
-                        );
-                    }
-                }
+                let channel = releasing_pheromone_comp.channel;
+                let ant_pos = self.entity_store.get_position(ant_id).unwrap().clone();
+                let strength =
+                    self.config.pheromone_strength * self.pheromone_boost_at(&ant_pos, channel);
+
+                self.increase_pheromone_strength_at(
+                    &ant_pos,
+                    channel,
+                    &IntensityComponent { strength },
+                );
             }
         }
     }
 
-    fn ants(&mut self) {
-        let mut new_positions: Vec<(EntityIndex, PositionComponent)> = vec![];
-        let mut new_adventurous: Vec<EntityIndex> = vec![];
+    fn pheromone_boost_at(&self, pos: &PositionComponent, channel: PheromoneChannel) -> u32 {
+        self.entity_store
+            .pheromone_channels
+            .get(channel)
+            .boosts
+            .iter()
+            .filter(|(entity_type, _)| {
+                self.entity_store
+                    .get_entities_with_type_at(pos, *entity_type)
+                    .next()
+                    .is_some()
+            })
+            .map(|(_, boost)| boost)
+            .product()
+    }
+
+    /// Ends tandem runs whose follower found the food or fell behind for
+    /// too long and pairs up informed ants at the base with nearby naive ones.
+    fn tandem_runs(&mut self) {
+        let mut waiting = HashSet::new();
+        for (ant_id, tandem) in &self.entity_store.tandems {
+            if tandem.role == TandemRole::Leader {
+                let leader_pos = self.entity_store.get_position(*ant_id).unwrap();
+                let follower_pos = self.entity_store.get_position(tandem.partner).unwrap();
+                if leader_pos.distance(follower_pos) > self.config.tandem_max_distance {
+                    waiting.insert(*ant_id);
+                }
+            }
+        }
+
+        for (ant_id, tandem) in &mut self.entity_store.tandems {
+            if waiting.contains(ant_id) {
+                tandem.waited += 1;
+            } else {
+                tandem.waited = 0;
+            }
+        }
+
+        let mut finished = vec![];
+        for (ant_id, tandem) in &self.entity_store.tandems {
+            let found_food = tandem.role == TandemRole::Follower
+                && self.entity_store.known_food.contains_key(ant_id);
+            let gave_up =
+                tandem.role == TandemRole::Leader && tandem.waited > self.config.tandem_max_wait;
+            if found_food || gave_up {
+                finished.push(*ant_id);
+                finished.push(tandem.partner);
+            }
+        }
 
-        for ant_id in self.entity_store.ants.keys() {
-            let pos = self.entity_store.get_position(*ant_id).unwrap();
+        for ant_id in finished {
+            self.entity_store.tandems.remove(&ant_id);
+        }
 
-            if self.entity_store.builders.get(&ant_id).is_some() {
-                new_positions.push((*ant_id, pos.clone()));
+        let mut leaders = vec![];
+        let mut followers = HashSet::new();
+        for ant_id in self
+            .entity_store
+            .query()
+            .with::<AntEntity>()
+            .without::<TandemComponent>()
+            .without::<CarryingFoodComponent>()
+            .without::<BuilderComponent>()
+            .iter()
+            .filter(|ant_id| self.entity_store.is_at(*ant_id, EntityType::Base))
+        {
+            if self.entity_store.has::<KnownFoodComponent>(ant_id) {
+                leaders.push(ant_id);
             } else {
-                let mut new_pos = PositionComponent::default();
-                let direction = self
-                    .entity_store
-                    .get_direction(*ant_id)
-                    .unwrap_or(&DirectionComponent { x: 1.0, y: 0.0 });
-                let direction = self.get_new_ant_direction(*ant_id, pos, direction);
-                new_pos.x = pos.x + direction.x;
-                new_pos.y = pos.y + direction.y;
+                followers.insert(ant_id);
+            }
+        }
 
-                // round to 0.01
-                new_pos.x = (new_pos.x * 100.0).round() / 100.0;
-                new_pos.y = (new_pos.y * 100.0).round() / 100.0;
+        for leader_id in leaders {
+            // Recruit the closest naive ant the leader can keep up with
+            let leader_pos = self.entity_store.get_position(leader_id).unwrap();
+            let follower_id = self
+                .entity_store
+                .get_entities_within(
+                    leader_pos,
+                    self.config.tandem_max_distance,
+                    Some(EntityType::Ant),
+                )
+                .into_iter()
+                .map(|(id, _)| id)
+                .find(|id| followers.contains(id));
+
+            if let Some(follower_id) = follower_id {
+                followers.remove(&follower_id);
+                self.entity_store.tandems.insert(
+                    leader_id,
+                    TandemComponent {
+                        partner: follower_id,
+                        role: TandemRole::Leader,
+                        waited: 0,
+                    },
+                );
+                self.entity_store.tandems.insert(
+                    follower_id,
+                    TandemComponent {
+                        partner: leader_id,
+                        role: TandemRole::Follower,
+                        waited: 0,
+                    },
+                );
+
+                if let Some(caste) = self.entity_store.castes.get_mut(&follower_id) {
+                    caste.caste = Caste::Forager;
+                }
+            }
+        }
+    }
 
-                new_positions.push((*ant_id, new_pos.clone()));
+    fn ants(&mut self) {
+        let mut new_positions = vec![];
+        let mut dead_ends = vec![];
+        let new_adventurous: Vec<EntityIndex> = vec![];
+        let ant_ids: Vec<EntityIndex> = self.entity_store.ants.keys().copied().collect();
+
+        let movement = self.movement();
+        if let Some(threads) = self.movement_threads {
+            let movement = &movement;
+            let chunk_size = ant_ids.len().div_ceil(threads.max(1)).max(1);
+            let chunks: Vec<_> = std::thread::scope(|scope| {
+                let handles: Vec<_> = ant_ids
+                    .chunks(chunk_size)
+                    .map(|chunk| scope.spawn(move || movement.move_ants(chunk)))
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect()
+            });
+
+            // Chunks are joined in order, so the result is the same
+            // as when moving all ants on one thread.
+            for (chunk_positions, chunk_dead_ends) in chunks {
+                new_positions.extend(chunk_positions);
+                dead_ends.extend(chunk_dead_ends);
             }
+        } else {
+            (new_positions, dead_ends) = movement.move_ants(&ant_ids);
+        }
+
+        // for ant_id in &ant_ids {
+        //     let is_adventurous: f32 = self.movement().ant_rng(*ant_id).gen();
+        //     if is_adventurous > 0.95 {
+        //         new_adventurous.push(*ant_id);
+        //     }
+        // }
 
-            // let is_adventurous: f32 = RNG.with(|rng| (*rng.borrow_mut()).gen());
-            // if is_adventurous > 0.95 {
-            //     new_adventurous.push(*ant_id);
-            // }
+        // Mark the cells ants had to turn around in so trail
+        // followers avoid them.
+        for (ant_id, pos) in dead_ends {
+            self.entity_store.events.emit(Event::AntReversed {
+                ant: ant_id,
+                pos: pos.clone(),
+            });
+            self.increase_pheromone_strength_at(
+                &pos,
+                PheromoneChannel::REPELLENT,
+                &IntensityComponent {
+                    strength: self.config.repellent_strength,
+                },
+            );
         }
 
-        for (ant_id, pos) in new_positions {
+        for (ant_id, unwrapped_pos) in new_positions {
+            let pos = self.wrap(&unwrapped_pos);
+            let old_pos = self.entity_store.get_position(ant_id).unwrap().clone();
+            let stays_put = self.entity_store.builders.contains_key(&ant_id)
+                || self.caste(ant_id) == Some(Caste::Reserve);
+            if !stays_put && old_pos == pos {
+                self.entity_store
+                    .events
+                    .emit(Event::AntBlocked { ant: ant_id });
+            }
+
             self.entity_store.update_position(ant_id, &pos);
+            if pos != unwrapped_pos {
+                // Keep heading the same way after crossing an edge
+                let ((x, y), (old_x, old_y)) = (unwrapped_pos.to_f64(), old_pos.to_f64());
+                self.entity_store.set_direction(
+                    ant_id,
+                    DirectionComponent {
+                        x: x - old_x,
+                        y: y - old_y,
+                    },
+                );
+            }
             self.handle_new_ant_pos(ant_id, &pos);
             self.release_pheromones(ant_id);
         }
 
         let mut depleted_adventurous: Vec<EntityIndex> = vec![];
-        for (ant_id, adventurous) in &mut self.entity_store.adventurous {
-            adventurous.ticks_left -= 1;
+        self.entity_store
+            .query_mut::<AdventurousComponent>()
+            .with::<AntEntity>()
+            .for_each(|ant_id, adventurous| {
+                adventurous.ticks_left = adventurous.ticks_left.saturating_sub(1);
 
-            if adventurous.ticks_left == 0 {
-                depleted_adventurous.push(*ant_id);
-            }
-        }
+                if adventurous.ticks_left == 0 {
+                    depleted_adventurous.push(ant_id);
+                }
+            });
 
         for depleted in depleted_adventurous {
             self.entity_store.adventurous.remove(&depleted);
@@ -439,79 +960,140 @@ impl Game {
     }
 
     fn pheromones(&mut self) {
-        let mut to_decrement = Vec::new();
-        for id in self.entity_store.intensities.keys() {
-            let pos = self.entity_store.get_position(*id).unwrap();
-            if self
-                .entity_store
-                .get_entities_with_type_at(pos, EntityType::Sugar)
-                .is_some()
-                || self
-                    .entity_store
-                    .get_entities_with_type_at(pos, EntityType::Base)
-                    .is_some()
-            {
-                continue;
-            }
-
-            to_decrement.push(*id);
-        }
+        let channels: Vec<PheromoneChannel> = self
+            .entity_store
+            .pheromone_channels
+            .iter()
+            .map(|(channel, _)| channel)
+            .collect();
 
-        let mut to_remove = Vec::new();
-        for id in to_decrement {
-            let intensity = self.entity_store.intensities.get_mut(&id).unwrap();
-            intensity.strength = intensity.strength.saturating_sub(1);
+        for channel in channels {
+            let expired = self.entity_store.evaporate(channel);
 
-            if intensity.strength == 0 {
-                to_remove.push(id);
+            for pos in expired {
+                self.entity_store
+                    .events
+                    .emit(Event::PheromoneExpired { pos, channel });
             }
         }
-
-        for id in to_remove {
-            self.remove_pheromone(id);
-        }
     }
 
-    pub fn add_deneubourg_walls(&mut self) {
-        let mut index;
-        let mut y;
-        for i in 0..5 {
-            index = self.entity_store.create_entity(EntityType::Wall);
-            y = if i == 0 || i == 4 { 1.5 } else { 0.5 };
-            self.entity_store
-                .update_position(index, &PositionComponent { x: f64::from(i), y });
+    /// Deliveries to the base during the last `delivery_rate_window` ticks.
+    #[must_use]
+    pub fn delivery_rate(&self) -> u32 {
+        self.entity_store.recent_deliveries.len() as u32
+    }
 
-            index = self.entity_store.create_entity(EntityType::Wall);
-            y = if i == 0 || i == 4 { 3.5 } else { 4.5 };
-            self.entity_store
-                .update_position(index, &PositionComponent { x: f64::from(i), y })
+    fn castes(&mut self) {
+        let tick = self.entity_store.tick;
+        while let Some(delivered) = self.entity_store.recent_deliveries.front() {
+            if tick - delivered < self.config.delivery_rate_window {
+                break;
+            }
+            self.entity_store.recent_deliveries.pop_front();
         }
 
-        // corners
-        index = self.entity_store.create_entity(EntityType::Wall);
+        // Ants switch between foraging and waiting in reserve when
+        // they're at the base, depending on how the delivery rate
+        // compares to their own response threshold. Until a full window
+        // has passed the rate is too low to say, so nobody is sent back.
+        let delivery_rate = self.delivery_rate();
+        let warmed_up = tick >= self.config.delivery_rate_window;
         self.entity_store
-            .update_position(index, &PositionComponent { x: 0.5, y: 0.5 });
-        index = self.entity_store.create_entity(EntityType::Wall);
-        self.entity_store
-            .update_position(index, &PositionComponent { x: 4.5, y: 0.5 });
-        index = self.entity_store.create_entity(EntityType::Wall);
-        self.entity_store
-            .update_position(index, &PositionComponent { x: 0.5, y: 4.5 });
-        index = self.entity_store.create_entity(EntityType::Wall);
-        self.entity_store
-            .update_position(index, &PositionComponent { x: 4.5, y: 4.5 });
+            .query_mut::<CasteComponent>()
+            .without::<CarryingFoodComponent>()
+            .filter(|store, ant_id| store.is_at(ant_id, EntityType::Base))
+            .for_each(|_, caste| match caste.caste {
+                Caste::Reserve if delivery_rate >= caste.threshold => {
+                    caste.caste = Caste::Forager;
+                }
+                Caste::Forager if warmed_up && delivery_rate < caste.threshold => {
+                    caste.caste = Caste::Reserve;
+                }
+                _ => {}
+            });
+    }
 
-        // middle
-        index = self.entity_store.create_entity(EntityType::Wall);
-        self.entity_store
-            .update_position(index, &PositionComponent { x: 2.5, y: 2.5 });
+    fn format_pheromone(cell: &PheromoneCell) -> String {
+        format!(
+            "{:7}|{:3}",
+            cell.intensity.strength, cell.generation.generation
+        )
     }
 
+    /// Runs every enabled system once.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the tick leaves the store inconsistent.
     pub fn tick(&mut self) {
-        self.pheromones();
-        self.ants();
+        self.entity_store.events.clear();
+        let mut scheduler = std::mem::take(&mut self.scheduler);
+        scheduler.run(self);
+        self.scheduler = scheduler;
         self.entity_store.pheromone_generation += 1;
-    }
+        self.entity_store.tick += 1;
+
+        if cfg!(debug_assertions) {
+            let violations = self.entity_store.validate();
+            assert!(
+                violations.is_empty(),
+                "Inconsistent store after tick {}:\n{}",
+                self.entity_store.tick,
+                violations
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+
+        self.record_history();
+    }
+
+    fn record_history(&mut self) {
+        if let Some(mut history) = self.history.take() {
+            history.record(self.entity_store.tick, || self.snapshot());
+            self.history = Some(history);
+        }
+    }
+
+    /// Keeps the last `depth` snapshots, taken every `interval` ticks,
+    /// so the game can be rewound at least `(depth - 1) * interval` ticks.
+    pub fn keep_history(&mut self, depth: usize, interval: u32) {
+        self.history = Some(History::new(depth, interval));
+        self.record_history();
+    }
+
+    /// Goes back `ticks` ticks by restoring the closest snapshot
+    /// before that and simulating forward from there.
+    ///
+    /// # Errors
+    ///
+    /// If no history is kept or it doesn't go back far enough.
+    pub fn rewind(&mut self, ticks: u32) -> Result<(), String> {
+        let target = self
+            .entity_store
+            .tick
+            .checked_sub(ticks)
+            .ok_or("Can't rewind past the first tick")?;
+        let history = self.history.as_mut().ok_or("No history is kept")?;
+        let (snapshot_tick, snapshot) = history
+            .latest_until(target)
+            .cloned()
+            .ok_or("No snapshot old enough to rewind that far")?;
+        history.forget_after(target);
+        self.restore(&snapshot).map_err(|e| e.to_string())?;
+
+        // Subscribers already saw the events of these ticks
+        let events = std::mem::take(&mut self.entity_store.events);
+        for _ in snapshot_tick..target {
+            self.tick();
+        }
+        self.entity_store.events = events;
+
+        Ok(())
+    }
 }
 
 impl fmt::Display for Game {
@@ -532,6 +1114,7 @@ impl fmt::Display for Game {
         // writeln!(f, "intensities: {:#?}", self.entity_store.intensities)?;
         // writeln!(f, "----------")?;
 
+        let channels = &self.entity_store.pheromone_channels;
         let integer_width = self.width.round() as u32;
         let integer_height = self.height.round() as u32;
         let separator = "|".to_owned()
@@ -539,90 +1122,79 @@ impl fmt::Display for Game {
                 .map(|_| "-----------|")
                 .collect::<String>();
 
-        writeln!(f, "{}", separator)?;
+        writeln!(f, "{separator}")?;
         for row in 0..integer_height {
+            let mut row_entities: Vec<&[EntityIndex]> = vec![&[]; integer_width as usize];
+            for (cell, ids) in self.entity_store.get_cells_in_rect(
+                &PositionComponent::new(0.0, f64::from(row)),
+                &PositionComponent::new(self.width, f64::from(row)),
+            ) {
+                if let Some(entities) = row_entities.get_mut(cell.x as usize) {
+                    *entities = ids;
+                }
+            }
+
             let mut row_1 = String::new();
-            let mut row_2 = String::new();
-            let mut row_3 = String::new();
+            let mut pheromone_rows = vec![String::new(); channels.len()];
             for col in 0..integer_width {
                 let mut cell_color = "white";
                 let mut cell_value_row_1: String = "           ".to_string();
-                let mut cell_value_row_2: String = "           ".to_string();
-                let mut cell_value_row_3: String = "           ".to_string();
-                let pos = PositionComponent {
-                    x: f64::from(col),
-                    y: f64::from(row),
-                };
-
-                if let Some(ids) = self.entity_store.get_entities_at(&pos) {
-                    for id in ids {
-                        match self.entity_store.entity_types.get(id) {
-                            Some(EntityType::Ant) => {
-                                cell_value_row_1 = format!("{}", id)
-                                    + &cell_value_row_1
-                                        [cell_value_row_1.char_indices().nth(1).unwrap().0..];
-                                cell_color = "red";
-                                if self.entity_store.carrying_food.get(&id).is_some() {
-                                    cell_color = "yellow";
-                                }
-                            }
-                            Some(EntityType::Sugar) => {
-                                cell_value_row_1 =
-                                    cell_value_row_1.chars().next().unwrap_or(' ').to_string()
-                                        + &"■■■■■■■■■■".to_string();
-                                cell_color = "green";
+                let mut cell_values_pheromones = vec!["           ".to_string(); channels.len()];
+                let pos = PositionComponent::new(f64::from(col), f64::from(row));
+
+                for id in row_entities[col as usize] {
+                    match self.entity_store.entity_types.get(id) {
+                        Some(EntityType::Ant) => {
+                            cell_value_row_1 = format!("{id}")
+                                + &cell_value_row_1
+                                    [cell_value_row_1.char_indices().nth(1).unwrap().0..];
+                            cell_color = "red";
+                            if self.entity_store.carrying_food.contains_key(id) {
+                                cell_color = "yellow";
                             }
-                            Some(EntityType::Base) => {
-                                cell_value_row_1 =
-                                    cell_value_row_1.chars().next().unwrap_or(' ').to_string()
-                                        + &"■■■■■■■■■■".to_string();
-                                cell_color = "blue";
-                            }
-                            Some(EntityType::Pheromone) => {
-                                match self.entity_store.pheromone_types.get(&id).unwrap() {
-                                    PheromoneType::Food => {
-                                        cell_value_row_2 = format!(
-                                            "{:7}|{:3}",
-                                            self.entity_store.intensities.get(id).unwrap().strength,
-                                            self.entity_store
-                                                .pheromone_generations
-                                                .get(id)
-                                                .unwrap()
-                                                .generation,
-                                        );
-                                    }
-                                    PheromoneType::Base => {
-                                        cell_value_row_3 = format!(
-                                            "{:7}|{:3}",
-                                            self.entity_store.intensities.get(id).unwrap().strength,
-                                            self.entity_store
-                                                .pheromone_generations
-                                                .get(id)
-                                                .unwrap()
-                                                .generation,
-                                        );
-                                    }
-                                }
-                            }
-                            Some(EntityType::Wall) => {
-                                cell_value_row_1 =
-                                    cell_value_row_1.chars().next().unwrap_or(' ').to_string()
-                                        + &"xxxxxxxxxx".to_string();
-                                cell_color = "red";
-                            }
-                            None => {}
                         }
+                        Some(EntityType::Sugar) => {
+                            cell_value_row_1 =
+                                cell_value_row_1.chars().next().unwrap_or(' ').to_string()
+                                    + "■■■■■■■■■■";
+                            cell_color = "green";
+                        }
+                        Some(EntityType::Base) => {
+                            cell_value_row_1 =
+                                cell_value_row_1.chars().next().unwrap_or(' ').to_string()
+                                    + "■■■■■■■■■■";
+                            cell_color = "blue";
+                        }
+                        Some(EntityType::Wall) => {
+                            cell_value_row_1 =
+                                cell_value_row_1.chars().next().unwrap_or(' ').to_string()
+                                    + "xxxxxxxxxx";
+                            cell_color = "red";
+                        }
+                        None => {}
                     }
                 }
-                row_1 += &format!("|{}", cell_value_row_1.color(cell_color));
-                row_2 += &format!("|{}", cell_value_row_2.color(cell_color));
-                row_3 += &format!("|{}", cell_value_row_3.color(cell_color));
+                for (channel, _) in channels.iter() {
+                    if let Some(cell) = self.entity_store.get_pheromone_with_type_at(&pos, channel)
+                    {
+                        cell_values_pheromones[channel.index()] = Self::format_pheromone(cell);
+                    }
+                }
+
+                write!(row_1, "|{}", cell_value_row_1.color(cell_color))?;
+                for ((_, settings), (row, value)) in channels
+                    .iter()
+                    .zip(pheromone_rows.iter_mut().zip(cell_values_pheromones))
+                {
+                    write!(row, "|{}", value.color(settings.color))?;
+                }
             }
 
-            writeln!(f, "{}|", row_1)?;
-            writeln!(f, "{}|", row_2)?;
-            writeln!(f, "{}|", row_3)?;
-            writeln!(f, "{}", separator)?;
+            writeln!(f, "{row_1}|")?;
+            for row in pheromone_rows {
+                writeln!(f, "{row}|")?;
+            }
+            writeln!(f, "{separator}")?;
         }
 
         Ok(())
@@ -632,45 +1204,40 @@ impl fmt::Display for Game {
 #[cfg(test)]
 mod game_tests {
     use super::*;
-
-    fn assert_greater_or_equal_then<T: fmt::Display + std::cmp::PartialOrd>(a: T, b: T) {
-        println!("{} >= {}", a, b);
+    use crate::builder::*;
+    use crate::channels::{PheromoneChannelSettings, PheromoneChannels};
+    use crate::components::{EdibleComponent, PheromoneGenerationComponent};
+    use crate::entities::{SugarEntity, WallEntity};
+    use crate::storage::DenseStorage;
+    use crate::validation::Violation;
+    use rand_chacha::ChaCha20Rng;
+
+    fn assert_greater_or_equal_then<T: Copy + fmt::Display + std::cmp::PartialOrd>(a: T, b: T) {
+        println!("{a} >= {b}");
         assert!(a >= b);
     }
 
-    fn init_game(width: f64, height: f64, ants: usize) -> Game {
-        let mut game = Game::init(EntityStore::default(), width, height);
-
+    fn game_builder(width: f64, height: f64, ants: u32) -> GameBuilder {
+        let mut builder = GameBuilder::new(width, height);
         for i in 0..ants {
-            let index = game.entity_store.create_entity(EntityType::Ant);
-            game.entity_store.update_position(
-                index,
-                &PositionComponent {
-                    x: (0.5 + i as f64) % width,
-                    y: height / 2.0,
-                },
-            );
+            builder.ant((0.5 + f64::from(i)) % width, height / 2.0);
         }
 
-        let index = game.entity_store.create_entity(EntityType::Base);
-        game.entity_store.update_position(
-            index,
-            &PositionComponent {
-                x: 0.5,
-                y: height / 2.0,
-            },
-        );
+        builder
+            .base(0.5, height / 2.0)
+            .sugar(width - 0.5, height / 2.0);
+        builder
+    }
 
-        let index = game.entity_store.create_entity(EntityType::Sugar);
-        game.entity_store.update_position(
-            index,
-            &PositionComponent {
-                x: width - 0.5,
-                y: height / 2.0,
-            },
-        );
+    fn init_game(width: f64, height: f64, ants: u32) -> Game {
+        game_builder(width, height, ants).build().unwrap()
+    }
 
-        game
+    fn init_deneubourg_game(width: f64, height: f64, ants: u32) -> Game {
+        game_builder(width, height, ants)
+            .deneubourg_walls()
+            .build()
+            .unwrap()
     }
 
     #[test]
@@ -686,8 +1253,7 @@ mod game_tests {
 
     #[test]
     fn test_5x5_optimal_deneubourg_walls_1_ant() {
-        let mut game = init_game(5.0, 5.0, 1);
-        game.add_deneubourg_walls();
+        let mut game = init_deneubourg_game(5.0, 5.0, 1);
 
         for _ in 0..300 {
             game.tick();
@@ -696,6 +1262,1271 @@ mod game_tests {
         assert_greater_or_equal_then(game.entity_store.food_in_base, 35);
     }
 
+    #[test]
+    fn test_trails_avoid_repellent() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        let pos = PositionComponent::new(2.5, 2.5);
+        let direction = DirectionComponent { x: 1.0, y: 0.0 };
+        game.entity_store.update_position(ant_id, &pos);
+
+        let trail_pos = PositionComponent::new(3.5, 2.5);
+        game.increase_pheromone_strength_at(
+            &trail_pos,
+            PheromoneChannel::FOOD,
+            &IntensityComponent { strength: 16 },
+        );
+        assert!(game
+            .movement()
+            .dir_to_strongest_adjecent_pheromone(
+                ant_id,
+                &pos,
+                &direction,
+                PheromoneChannel::FOOD,
+                false
+            )
+            .is_some());
+
+        game.increase_pheromone_strength_at(
+            &trail_pos,
+            PheromoneChannel::REPELLENT,
+            &IntensityComponent { strength: 32 },
+        );
+        assert!(game
+            .movement()
+            .dir_to_strongest_adjecent_pheromone(
+                ant_id,
+                &pos,
+                &direction,
+                PheromoneChannel::FOOD,
+                false
+            )
+            .is_none());
+
+        // Wandering ants avoid it as well
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        for _ in 0..20 {
+            let (dir, dead_end) = game
+                .movement()
+                .get_new_ant_direction(ant_id, &pos, &direction, &mut rng);
+            let new_pos = pos.offset(dir.x, dir.y);
+            assert!(dead_end || CoarsePositionComponent::from(&new_pos) != (&trail_pos).into());
+        }
+    }
+
+    #[test]
+    fn test_ants_step_onto_adjacent_goal() {
+        let mut game = init_deneubourg_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        let direction = DirectionComponent { x: 0.0, y: -1.0 };
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+
+        // Heading into the wall next to the sugar
+        let pos = PositionComponent::new(3.5, 1.5);
+        game.entity_store.update_position(ant_id, &pos);
+        let (dir, dead_end) = game
+            .movement()
+            .get_new_ant_direction(ant_id, &pos, &direction, &mut rng);
+        assert_eq!((dir.x, dir.y, dead_end), (1.0, 1.0, false));
+
+        // Ants carrying food head for the base instead
+        let pos = PositionComponent::new(1.5, 2.5);
+        game.entity_store.update_position(ant_id, &pos);
+        game.entity_store
+            .carrying_food
+            .insert(ant_id, CarryingFoodComponent { amount: 1 });
+        let (dir, _) = game
+            .movement()
+            .get_new_ant_direction(ant_id, &pos, &direction, &mut rng);
+        assert_eq!((dir.x, dir.y), (-1.0, 0.0));
+    }
+
+    #[test]
+    fn test_registered_channel_evaporates() {
+        let mut game = init_game(5.0, 5.0, 0);
+        let mut settings = PheromoneChannelSettings::new("alarm");
+        settings.evaporation_rate = 4;
+        let alarm = game
+            .entity_store
+            .pheromone_channels
+            .register(settings)
+            .unwrap();
+
+        let pos = PositionComponent::new(2.5, 2.5);
+        game.increase_pheromone_strength_at(&pos, alarm, &IntensityComponent { strength: 16 });
+
+        for _ in 0..3 {
+            game.pheromones();
+        }
+        assert!(game
+            .entity_store
+            .get_pheromone_with_type_at(&pos, alarm)
+            .is_some());
+
+        game.pheromones();
+        assert!(game
+            .entity_store
+            .get_pheromone_with_type_at(&pos, alarm)
+            .is_none());
+
+        // Behaviours refer to the built-in channels by their index
+        let mut channels = PheromoneChannels::new();
+        assert!(channels.check_registration("alarm").is_err());
+        channels
+            .register(PheromoneChannelSettings::new("food"))
+            .unwrap();
+        assert!(channels.check_registration("food").is_err());
+        assert!(channels.check_registration("repellent").is_err());
+        assert!(channels.check_registration("base").is_ok());
+    }
+
+    #[test]
+    fn test_position_resolution() {
+        let mut config = SimulationConfig::default();
+        config.set("position_resolution", "2").unwrap();
+        let mut game = game_builder(10.0, 10.0, 10).config(config).build().unwrap();
+        for _ in 0..20 {
+            game.tick();
+        }
+
+        for ant_id in game.entity_store.ants.keys() {
+            let pos = game.entity_store.get_position(*ant_id).unwrap();
+            let (x, y) = pos.to_f64();
+            assert_eq!(*pos, PositionComponent::rounded(x, y, 2));
+        }
+    }
+
+    #[test]
+    fn test_declared_channels() {
+        let mut config = SimulationConfig::default();
+        config
+            .set("channels", "alarm:4:red trail:1:bright_yellow")
+            .unwrap();
+        for value in [
+            "food:1:red",
+            "alarm:1:red alarm:2:red",
+            "alarm:x:red",
+            "alarm:1:pink",
+            "alarm:1",
+            ":1:red",
+        ] {
+            assert!(config.set("channels", value).is_err());
+        }
+        let read = SimulationConfig::read(config.to_string().as_bytes()).unwrap();
+        assert_eq!(read, config);
+
+        let mut game = game_builder(5.0, 5.0, 1).config(config).build().unwrap();
+        let alarm = game.entity_store.pheromone_channels.find("alarm").unwrap();
+        assert_eq!(alarm.index(), 3);
+        assert_eq!(
+            game.entity_store
+                .pheromone_channels
+                .get(alarm)
+                .evaporation_rate,
+            4
+        );
+        game.tick();
+        let saved = game.snapshot();
+        assert_eq!(Game::load(saved.as_bytes()).unwrap().snapshot(), saved);
+
+        // Configs that skip `set` are checked when the game is built
+        let config = SimulationConfig {
+            channels: "repellent:1:red".parse().unwrap(),
+            ..SimulationConfig::default()
+        };
+        assert!(matches!(
+            game_builder(5.0, 5.0, 1).config(config).build(),
+            Err(BuildError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_evaporation_exempt_follows_sugar() {
+        let mut game = init_game(5.0, 5.0, 0);
+        let sugar_id = *game.entity_store.sugars.keys().next().unwrap();
+        let old_pos = PositionComponent::new(4.5, 2.5);
+        let new_pos = PositionComponent::new(3.5, 2.5);
+        let intensity = IntensityComponent { strength: 2 };
+        game.increase_pheromone_strength_at(&old_pos, PheromoneChannel::FOOD, &intensity);
+        game.increase_pheromone_strength_at(&new_pos, PheromoneChannel::FOOD, &intensity);
+
+        game.pheromones();
+        game.pheromones();
+        let strength_at = |game: &Game, pos| {
+            game.entity_store
+                .get_pheromone_with_type_at(pos, PheromoneChannel::FOOD)
+                .map(|cell| cell.intensity.strength)
+        };
+        assert_eq!(strength_at(&game, &old_pos), Some(2));
+        assert_eq!(strength_at(&game, &new_pos), None);
+
+        game.increase_pheromone_strength_at(&new_pos, PheromoneChannel::FOOD, &intensity);
+        game.entity_store.update_position(sugar_id, &new_pos);
+        game.pheromones();
+        game.pheromones();
+        assert_eq!(strength_at(&game, &old_pos), None);
+        assert_eq!(strength_at(&game, &new_pos), Some(2));
+    }
+
+    #[test]
+    fn test_scouts_dont_lay_trails() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        game.entity_store.castes.insert(
+            ant_id,
+            CasteComponent {
+                caste: Caste::Scout,
+                threshold: 1,
+            },
+        );
+
+        game.tick();
+        for (channel, _) in game.entity_store.pheromone_channels.iter() {
+            for x in 0..5 {
+                for y in 0..5 {
+                    let pos = PositionComponent::new(f64::from(x), f64::from(y));
+                    assert!(game
+                        .entity_store
+                        .get_pheromone_with_type_at(&pos, channel)
+                        .is_none());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reserve_recruited_by_deliveries() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        game.entity_store.castes.insert(
+            ant_id,
+            CasteComponent {
+                caste: Caste::Reserve,
+                threshold: 2,
+            },
+        );
+        let base = game.entity_store.get_position(ant_id).unwrap().clone();
+
+        game.tick();
+        assert_eq!(game.entity_store.get_position(ant_id), Some(&base));
+        assert_eq!(game.caste(ant_id), Some(Caste::Reserve));
+
+        game.entity_store.recent_deliveries.extend(&[0, 0]);
+        game.tick();
+        assert_eq!(game.caste(ant_id), Some(Caste::Forager));
+    }
+
+    #[test]
+    fn test_foragers_kept_while_warming_up() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        game.entity_store.castes.insert(
+            ant_id,
+            CasteComponent {
+                caste: Caste::Forager,
+                threshold: 1,
+            },
+        );
+
+        game.castes();
+        assert_eq!(game.caste(ant_id), Some(Caste::Forager));
+
+        game.entity_store.tick = game.config.delivery_rate_window;
+        game.castes();
+        assert_eq!(game.caste(ant_id), Some(Caste::Reserve));
+    }
+
+    #[test]
+    fn test_carrying_capacity() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        let sugar_id = *game.entity_store.sugars.keys().next().unwrap();
+        game.entity_store.edibles.insert(
+            sugar_id,
+            EdibleComponent {
+                weight: 2,
+                items: None,
+            },
+        );
+        game.entity_store
+            .capacities
+            .insert(ant_id, CapacityComponent { capacity: 5 });
+
+        let sugar_pos = game.entity_store.get_position(sugar_id).unwrap().clone();
+        game.entity_store.update_position(ant_id, &sugar_pos);
+        game.handle_new_ant_pos(ant_id, &sugar_pos);
+        assert_eq!(
+            game.entity_store.carrying_food.get(&ant_id),
+            Some(&CarryingFoodComponent { amount: 4 })
+        );
+        assert!(game.movement().ant_speed(ant_id) < 1.0);
+
+        let base_pos = PositionComponent::new(0.5, sugar_pos.y.to_f64());
+        game.entity_store.update_position(ant_id, &base_pos);
+        game.handle_new_ant_pos(ant_id, &base_pos);
+        assert_eq!(game.entity_store.food_in_base, 4);
+        assert!(!game.entity_store.carrying_food.contains_key(&ant_id));
+    }
+
+    #[test]
+    fn test_slowed_ants_stay_out_of_walls() {
+        // Full steps from the ant only reach the free corner, but half
+        // steps mostly end up in the wall above
+        let mut game = GameBuilder::new(2.0, 2.0)
+            .ant(0.5, 0.95)
+            .wall(1.5, 0.5)
+            .wall(0.5, 1.5)
+            .build()
+            .unwrap();
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        game.entity_store
+            .capacities
+            .insert(ant_id, CapacityComponent { capacity: 2 });
+        game.entity_store
+            .carrying_food
+            .insert(ant_id, CarryingFoodComponent { amount: 3 });
+        assert!((game.movement().ant_speed(ant_id) - 0.5).abs() < f64::EPSILON);
+
+        let pos = PositionComponent::new(0.5, 0.95);
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        for _ in 0..20 {
+            let (new_pos, _) = game.movement().next_ant_pos(ant_id, &pos, &mut rng);
+            assert!(game.movement().pos_can_be_occupied(&new_pos));
+        }
+    }
+
+    #[test]
+    fn test_game_builder() {
+        let pos = PositionComponent::new(5.5, 1.0);
+        assert_eq!(
+            GameBuilder::new(5.0, 5.0).base(5.5, 1.0).build().err(),
+            Some(BuildError::OutOfBounds {
+                entity_type: EntityType::Base,
+                pos
+            })
+        );
+
+        let pos = PositionComponent::new(2.5, 2.5);
+        assert_eq!(
+            GameBuilder::new(5.0, 5.0)
+                .ant(2.5, 2.5)
+                .wall_line((0.0, 2.0), (4.0, 2.0))
+                .build()
+                .err(),
+            Some(BuildError::Overlap {
+                entity_type: EntityType::Wall,
+                other: EntityType::Ant,
+                pos
+            })
+        );
+        assert!(GameBuilder::new(5.0, 5.0)
+            .base(1.5, 1.5)
+            .sugar(1.2, 1.7)
+            .build()
+            .is_err());
+        assert_eq!(
+            GameBuilder::new(5.0, 5.0)
+                .sugar_with_quantity(2.5, 2.5, 0)
+                .build()
+                .err(),
+            Some(BuildError::EmptySugar {
+                pos: PositionComponent::new(2.5, 2.5)
+            })
+        );
+        assert!(GameBuilder::new(5.0, 5.0)
+            .wall_rect((0.0, 0.0), (4.0, 4.0))
+            .random_ants(1)
+            .build()
+            .is_err());
+
+        let game = GameBuilder::new(5.0, 5.0)
+            .base(4.5, 4.5)
+            .wall_rect((0.0, 0.0), (3.0, 4.0))
+            .random_ants(20)
+            .build()
+            .unwrap();
+        assert_eq!(game.entity_store.walls.len(), 20);
+        for ant_id in game.entity_store.ants.keys() {
+            let pos = game.entity_store.get_position(*ant_id).unwrap();
+            assert_eq!(CoarsePositionComponent::from(pos).x, 4);
+        }
+    }
+
+    #[test]
+    fn test_run_until() {
+        use crate::stop::*;
+
+        let mut game = init_game(5.0, 5.0, 1);
+        let stop = game
+            .run_until(vec![
+                StopCondition::Ticks(300),
+                StopCondition::FoodInBase(10),
+            ])
+            .unwrap();
+        assert_eq!(stop.condition, StopCondition::FoodInBase(10));
+        assert!(stop.tick < 300);
+        assert_eq!(game.entity_store.food_in_base, 10);
+
+        // Conditions are checked before the first tick
+        let stop = game
+            .run_until(vec![StopCondition::FoodInBase(5), StopCondition::Ticks(0)])
+            .unwrap();
+        assert_eq!(stop.tick, 0);
+        assert_eq!(stop.condition, StopCondition::FoodInBase(5));
+
+        let mut game = init_game(5.0, 5.0, 1);
+        let stop = game
+            .run_until(vec![
+                StopCondition::Ticks(300),
+                StopCondition::Converged(20),
+            ])
+            .unwrap();
+        assert_eq!(stop.condition, StopCondition::Converged(20));
+
+        let mut game = GameBuilder::new(5.0, 5.0)
+            .ant(0.5, 2.5)
+            .base(0.5, 2.5)
+            .sugar_with_quantity(4.5, 2.5, 2)
+            .build()
+            .unwrap();
+        let stop = game
+            .run_until(vec![StopCondition::SugarDepleted, StopCondition::AntsDead])
+            .unwrap();
+        assert_eq!(stop.condition, StopCondition::SugarDepleted);
+        assert!(game.entity_store.sugars.is_empty());
+
+        let mut game = init_game(5.0, 5.0, 1);
+        let stop = game
+            .run_until(vec![StopCondition::Timeout(std::time::Duration::ZERO)])
+            .unwrap();
+        assert_eq!(stop.tick, 0);
+        assert!(game.run_until(vec![]).is_err());
+        assert_eq!(game.entity_store.tick, 0);
+
+        let mut game = init_game(5.0, 5.0, 0);
+        let stop = game.run_until(vec![StopCondition::AntsDead]).unwrap();
+        assert_eq!(stop.condition, StopCondition::AntsDead);
+    }
+
+    #[test]
+    fn test_sugar_runs_out() {
+        let mut game = GameBuilder::new(5.0, 5.0)
+            .ant(0.5, 2.5)
+            .ant(1.5, 2.5)
+            .base(0.5, 2.5)
+            .sugar_with_quantity(4.5, 2.5, 3)
+            .build()
+            .unwrap();
+        let ant_ids: Vec<EntityIndex> = game.entity_store.ants.keys().copied().collect();
+        let sugar_id = *game.entity_store.sugars.keys().next().unwrap();
+        game.entity_store
+            .capacities
+            .insert(ant_ids[0], CapacityComponent { capacity: 2 });
+        game.entity_store
+            .capacities
+            .insert(ant_ids[1], CapacityComponent { capacity: 2 });
+
+        let sugar_pos = PositionComponent::new(4.5, 2.5);
+        game.handle_new_ant_pos(ant_ids[0], &sugar_pos);
+        assert_eq!(game.entity_store.edibles[&sugar_id].items, Some(1));
+        assert!(game.entity_store.known_food.contains_key(&ant_ids[0]));
+
+        game.handle_new_ant_pos(ant_ids[1], &sugar_pos);
+        assert_eq!(
+            game.entity_store.carrying_food.get(&ant_ids[1]),
+            Some(&CarryingFoodComponent { amount: 1 })
+        );
+        assert!(game.entity_store.sugars.is_empty());
+
+        // Nobody leads others to the depleted source anymore
+        assert!(game.entity_store.known_food.is_empty());
+        assert!(game.entity_store.events.drain().any(|event| event
+            == Event::EntityDestroyed {
+                id: sugar_id,
+                entity_type: EntityType::Sugar
+            }));
+    }
+
+    #[test]
+    fn test_torus() {
+        let mut game = GameBuilder::new(5.0, 5.0)
+            .topology(Topology::Torus)
+            .ant(4.9, 2.5)
+            .build()
+            .unwrap();
+        let outside = PositionComponent::new(5.2, -0.3);
+        assert_eq!(game.wrap(&outside), PositionComponent::new(0.2, 4.7));
+        assert!(game.movement().pos_can_be_occupied(&outside));
+
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        game.entity_store
+            .set_direction(ant_id, DirectionComponent { x: 1.0, y: 0.0 });
+        let mut crossed = false;
+        for _ in 0..50 {
+            let x = game.entity_store.get_position(ant_id).unwrap().x;
+            game.tick();
+            let new_x = game.entity_store.get_position(ant_id).unwrap().x;
+            crossed |= (x.to_f64() - new_x.to_f64()).abs() > 2.0;
+        }
+        assert!(crossed);
+
+        game.topology = Topology::Bounded;
+        assert!(!game.movement().pos_can_be_occupied(&outside));
+    }
+
+    #[test]
+    fn test_tandem_run() {
+        let mut game = init_game(5.0, 5.0, 2);
+        game.scheduler.set_enabled("tandem_runs", true);
+        let ant_ids: Vec<EntityIndex> = game.entity_store.ants.keys().copied().collect();
+        let (leader_id, follower_id) = (ant_ids[0], ant_ids[1]);
+        let base_pos = game.entity_store.get_position(leader_id).unwrap().clone();
+        let food_pos = PositionComponent::new(4.5, 2.5);
+        game.entity_store
+            .known_food
+            .insert(leader_id, KnownFoodComponent { pos: food_pos });
+        game.entity_store.update_position(follower_id, &base_pos);
+
+        game.tandem_runs();
+        assert_eq!(
+            game.entity_store.tandems.get(&leader_id),
+            Some(&TandemComponent {
+                partner: follower_id,
+                role: TandemRole::Leader,
+                waited: 0,
+            })
+        );
+
+        // The leader waits for a follower that fell behind
+        let far_pos = PositionComponent::new(3.5, 0.5);
+        game.entity_store.update_position(follower_id, &far_pos);
+        let (leader_pos, _) = game.movement().tandem_step(
+            leader_id,
+            follower_id,
+            &mut game.movement().ant_rng(leader_id),
+        );
+        assert_eq!(leader_pos, base_pos);
+
+        game.entity_store.update_position(follower_id, &base_pos);
+        for _ in 0..10 {
+            game.tick();
+        }
+        assert!(game.entity_store.known_food.contains_key(&follower_id));
+        assert!(game.entity_store.tandems.is_empty());
+    }
+
+    #[test]
+    fn test_tandem_run_gives_up() {
+        let mut game = init_game(5.0, 5.0, 2);
+        game.config.tandem_max_wait = 3;
+        let ant_ids: Vec<EntityIndex> = game.entity_store.ants.keys().copied().collect();
+        let (leader_id, follower_id) = (ant_ids[0], ant_ids[1]);
+        let base_pos = game.entity_store.get_position(leader_id).unwrap().clone();
+        game.entity_store.known_food.insert(
+            leader_id,
+            KnownFoodComponent {
+                pos: PositionComponent::new(4.5, 2.5),
+            },
+        );
+        game.entity_store.update_position(follower_id, &base_pos);
+        game.tandem_runs();
+        assert_eq!(game.entity_store.tandems.len(), 2);
+
+        // The follower is stuck far away from the waiting leader
+        let far_pos = PositionComponent::new(3.5, 0.5);
+        game.entity_store.update_position(follower_id, &far_pos);
+        for _ in 0..3 {
+            game.tandem_runs();
+        }
+        assert_eq!(game.entity_store.tandems.get(&leader_id).unwrap().waited, 3);
+
+        game.tandem_runs();
+        assert!(game.entity_store.tandems.is_empty());
+    }
+
+    #[test]
+    fn test_is_in_world() {
+        let bounded = EntityStore::bounded(5.0, 4.0);
+        assert!(bounded.is_in_world(&PositionComponent::new(4.9, 3.9)));
+        assert!(!bounded.is_in_world(&PositionComponent::new(5.0, 0.5)));
+        assert!(!bounded.is_in_world(&PositionComponent::new(0.5, 4.0)));
+        assert!(!bounded.is_in_world(&PositionComponent::new(-0.5, 0.5)));
+        assert!(EntityStore::default().is_in_world(&PositionComponent::new(500.0, 0.5)));
+    }
+
+    #[test]
+    fn test_short_memory() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        game.entity_store.clear_memory(ant_id);
+        let cell = |x: f64| PositionComponent::new(x + 0.5, 0.5);
+        for x in [0.0, 1.0, 0.0, 2.0, 3.0] {
+            game.entity_store.add_to_short_memory(ant_id, &cell(x), 3);
+        }
+
+        // The first visit of cell 0 was forgotten, the second one wasn't
+        assert!(game.entity_store.in_short_memory(ant_id, &cell(0.0)));
+        assert!(!game.entity_store.in_short_memory(ant_id, &cell(1.0)));
+        assert!(game.entity_store.in_short_memory(ant_id, &cell(3.0)));
+
+        game.entity_store.add_to_short_memory(ant_id, &cell(4.0), 1);
+        for x in [0.0, 2.0, 3.0] {
+            assert!(!game.entity_store.in_short_memory(ant_id, &cell(x)));
+        }
+        assert!(game.entity_store.in_short_memory(ant_id, &cell(4.0)));
+    }
+
+    #[test]
+    fn test_dense_storage_reuses_slot() {
+        let mut storage = DenseStorage::default();
+        let old_id: EntityIndex = "3.0".parse().unwrap();
+        let new_id: EntityIndex = "3.1".parse().unwrap();
+        assert_eq!(storage.insert(old_id, 1), None);
+        assert_eq!(storage.insert(old_id, 2), Some(1));
+        assert_eq!(storage.len(), 1);
+
+        // The stale entity is replaced without being removed first
+        assert_eq!(storage.insert(new_id, 3), None);
+        assert_eq!(storage.len(), 1);
+        assert!(!storage.contains_key(&old_id));
+        assert_eq!(storage.remove(&new_id), Some(3));
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn test_destroy_entity() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        let pos = game.entity_store.get_position(ant_id).unwrap().clone();
+        game.tick();
+
+        game.entity_store.destroy_entity(ant_id);
+        assert!(game.entity_store.ants.is_empty());
+        assert!(game.entity_store.get_position(ant_id).is_none());
+        assert!(game
+            .entity_store
+            .get_entities_with_type_at(&pos, EntityType::Ant)
+            .next()
+            .is_none());
+
+        // The game keeps running without it
+        game.tick();
+    }
+
+    #[test]
+    fn test_stale_handles() {
+        let mut game = init_game(5.0, 5.0, 0);
+        let old_id = game.entity_store.create_entity(EntityType::Wall);
+        game.entity_store.destroy_entity(old_id);
+
+        // The slot of the destroyed wall is reused
+        let new_id = game.entity_store.create_entity(EntityType::Wall);
+        assert_eq!(format!("{old_id}"), format!("{}", new_id));
+        assert_ne!(old_id, new_id);
+        assert!(!game.entity_store.is_alive(old_id));
+        assert!(game.entity_store.is_alive(new_id));
+        assert!(game.entity_store.get_position(old_id).is_none());
+        assert!(!game.entity_store.impenetrables.contains_key(&old_id));
+        assert!(game.entity_store.impenetrables.contains_key(&new_id));
+    }
+
+    #[test]
+    fn test_pheromone_reinforcement() {
+        let mut game = init_game(5.0, 5.0, 0);
+        let pos = PositionComponent::new(2.5, 2.5);
+        let sugar_pos = PositionComponent::new(4.5, 2.5);
+        for pos in &[&pos, &sugar_pos] {
+            game.increase_pheromone_strength_at(
+                pos,
+                PheromoneChannel::FOOD,
+                &IntensityComponent { strength: 16 },
+            );
+        }
+
+        // Reinforcing keeps the generation of the existing pheromone
+        game.entity_store.pheromone_generation = 5;
+        game.increase_pheromone_strength_at(
+            &pos,
+            PheromoneChannel::FOOD,
+            &IntensityComponent { strength: 16 },
+        );
+        assert_eq!(
+            game.entity_store
+                .get_pheromone_with_type_at(&pos, PheromoneChannel::FOOD),
+            Some(&PheromoneCell {
+                intensity: IntensityComponent { strength: 32 },
+                generation: PheromoneGenerationComponent { generation: 0 },
+            })
+        );
+
+        // Pheromones on sugar don't evaporate
+        game.pheromones();
+        let strength_at = |pos| {
+            game.entity_store
+                .get_pheromone_with_type_at(pos, PheromoneChannel::FOOD)
+                .unwrap()
+                .intensity
+                .strength
+        };
+        assert_eq!(strength_at(&pos), 31);
+        assert_eq!(strength_at(&sugar_pos), 16);
+    }
+
+    #[test]
+    fn test_spatial_index() {
+        let mut bounded = init_game(10.0, 10.0, 20);
+        let mut unbounded = init_game(10.0, 10.0, 0);
+        unbounded.entity_store = EntityStore::default();
+        for (id, entity_type) in bounded.entity_store.entity_types.iter() {
+            let pos = bounded.entity_store.get_position(*id).unwrap();
+            let new_id = unbounded.entity_store.create_entity(*entity_type);
+            unbounded.entity_store.update_position(new_id, pos);
+        }
+
+        let cells = |game: &Game| {
+            game.entity_store
+                .get_cells_in_rect(
+                    &PositionComponent::new(2.0, 4.0),
+                    &PositionComponent::new(7.5, 5.5),
+                )
+                .map(|(cell, ids)| (cell, ids.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            cells(&bounded),
+            (2..8)
+                .map(|x| (CoarsePositionComponent { x, y: 5 }, 2))
+                .collect::<Vec<_>>()
+        );
+
+        // Both games have to draw the same random numbers
+        bounded.movement_threads = Some(1);
+        unbounded.movement_threads = Some(1);
+        for _ in 0..20 {
+            bounded.tick();
+            unbounded.tick();
+            assert_eq!(cells(&bounded), cells(&unbounded));
+        }
+    }
+
+    #[test]
+    fn test_spatial_queries() {
+        let mut game = init_game(10.0, 10.0, 0);
+        let mut ids = vec![];
+        for (x, y) in &[(5.5, 5.5), (5.9, 5.5), (6.5, 6.5), (3.5, 5.5), (9.5, 9.5)] {
+            let id = game.entity_store.create_entity(EntityType::Ant);
+            game.entity_store
+                .update_position(id, &PositionComponent::new(*x, *y));
+            ids.push(id);
+        }
+
+        let pos = PositionComponent::new(5.5, 5.5);
+        let within: Vec<EntityIndex> = game
+            .entity_store
+            .get_entities_within(&pos, 2.0, Some(EntityType::Ant))
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(within, vec![ids[0], ids[1], ids[2], ids[3]]);
+
+        // The base is in range but isn't an ant
+        assert_eq!(
+            game.entity_store
+                .get_entities_within(&PositionComponent::new(0.5, 5.0), 1.0, None)
+                .len(),
+            1
+        );
+
+        let in_rect = game.entity_store.get_entities_in_rect(
+            &PositionComponent::new(3.0, 5.0),
+            &PositionComponent::new(6.0, 6.0),
+            Some(EntityType::Ant),
+        );
+        assert_eq!(in_rect, vec![ids[3], ids[0], ids[1], ids[2]]);
+    }
+
+    #[test]
+    fn test_events() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let events = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let subscriber_events = events.clone();
+        game.entity_store
+            .events
+            .subscribe(move |event| subscriber_events.lock().unwrap().push(event.clone()));
+
+        for _ in 0..30 {
+            game.tick();
+        }
+
+        let count = |matches: fn(&Event) -> bool| {
+            events.lock().unwrap().iter().filter(|e| matches(e)).count()
+        };
+        assert!(count(|e| matches!(e, Event::FoodPickedUp { .. })) > 0);
+        assert_eq!(
+            count(|e| matches!(e, Event::FoodDelivered { .. })) as u32,
+            game.entity_store.food_in_base
+        );
+        assert!(count(|e| matches!(e, Event::PheromoneCreated { .. })) > 0);
+        assert!(count(|e| matches!(e, Event::PheromoneMerged { .. })) > 0);
+        assert!(count(|e| matches!(e, Event::PheromoneExpired { .. })) > 0);
+
+        // Draining only returns the events of the last tick
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        game.tick();
+        game.entity_store.destroy_entity(ant_id);
+        let drained: Vec<Event> = game.entity_store.events.drain().collect();
+        assert!(drained.len() < events.lock().unwrap().len());
+        assert_eq!(
+            drained.last(),
+            Some(&Event::EntityDestroyed {
+                id: ant_id,
+                entity_type: EntityType::Ant
+            })
+        );
+        assert!(game.entity_store.events.drain().next().is_none());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut game = init_deneubourg_game(10.0, 10.0, 20);
+        game.scheduler.set_enabled("tandem_runs", true);
+        let ant_ids: Vec<EntityIndex> = game.entity_store.ants.keys().copied().collect();
+        for (i, ant_id) in ant_ids.iter().enumerate() {
+            let caste = if i % 2 == 0 {
+                Caste::Scout
+            } else {
+                Caste::Forager
+            };
+            game.entity_store.castes.insert(
+                *ant_id,
+                CasteComponent {
+                    caste,
+                    threshold: 1,
+                },
+            );
+        }
+        for _ in 0..40 {
+            game.tick();
+        }
+
+        let save = |game: &Game| {
+            let mut buffer = vec![];
+            game.save(&mut buffer).unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let saved = save(&game);
+        let mut loaded = Game::load(saved.as_bytes()).unwrap();
+        assert_eq!(save(&loaded), saved);
+
+        // Continuing the loaded game is the same as never stopping
+        for _ in 0..60 {
+            game.tick();
+            loaded.tick();
+        }
+        assert_eq!(save(&loaded), save(&game));
+        assert!(Game::load("ants save 0".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_save_fresh_game_with_custom_system() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let moved = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        game.scheduler.add(Box::new(AntMovedSystem { moved }));
+        game.scheduler.set_enabled("ant_moved", false);
+
+        let saved = game.snapshot();
+        let loaded = Game::load(saved.as_bytes()).unwrap();
+        assert!(loaded
+            .scheduler
+            .systems()
+            .all(|(name, _)| name != "ant_moved"));
+
+        // Restoring keeps custom systems as they are
+        game.tick();
+        game.restore(&saved).unwrap();
+        assert_eq!(game.snapshot(), saved);
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_saves() {
+        let game = init_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        let ant_id = format!("{}.{}", ant_id.index(), ant_id.generation());
+        let saved = game.snapshot();
+        let with_line = |extra: &str| format!("{saved}{extra}\n");
+        let with_slots = |slots: &str| {
+            saved
+                .lines()
+                .map(|line| {
+                    if line.starts_with("slots ") {
+                        slots
+                    } else {
+                        line
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        assert!(Game::load(with_line(&format!("position {ant_id} 4.5 2.5")).as_bytes()).is_ok());
+        assert!(Game::load(with_line(&format!("position {ant_id} 5.5 2.5")).as_bytes()).is_err());
+        assert!(Game::load(with_line(&format!("position {ant_id} -0.5 2.5")).as_bytes()).is_err());
+        assert!(Game::load(with_slots("slots - -").as_bytes()).is_err());
+        assert!(Game::load(with_line("pheromone food 4 4 16 0").as_bytes()).is_ok());
+        assert!(Game::load(with_line("pheromone food 20 4 16 0").as_bytes()).is_err());
+        assert!(Game::load(with_line(&format!("known_food {ant_id} 9.5 2.5")).as_bytes()).is_err());
+        assert!(Game::load(with_slots("slots 0,0,0 3").as_bytes()).is_err());
+        assert!(Game::load(with_line(&format!("edible {ant_id} 0")).as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_config() {
+        let mut config = SimulationConfig::default();
+        config.set("pheromone_strength", "32").unwrap();
+        assert!(config.set("pheromone_strength", "strong").is_err());
+        assert!(config.set("unknown", "1").is_err());
+        for (key, value) in [
+            ("pheromone_ticks", "0"),
+            ("memory_size", "0"),
+            ("turn_std_dev", "0"),
+            ("turn_std_dev", "-0.5"),
+            ("turn_std_dev", "NaN"),
+            ("direction_attempts", "0"),
+            ("delivery_rate_window", "0"),
+            ("position_resolution", "0"),
+            ("position_resolution", "3"),
+        ] {
+            assert!(config.set(key, value).is_err());
+        }
+        assert_eq!(config.memory_size, 16);
+        let read = SimulationConfig::read(config.to_string().as_bytes()).unwrap();
+        assert_eq!(read, config);
+        assert!(SimulationConfig::read("memory_size 4".as_bytes()).is_err());
+
+        // Released pheromones scale with the configured strength
+        let food_strengths = |config: SimulationConfig| {
+            let mut game = init_game(10.0, 10.0, 10);
+            game.config = config;
+            game.tick();
+            let field = &game.entity_store.pheromone_fields[PheromoneChannel::FOOD.index()];
+            field
+                .iter()
+                .map(|(pos, cell)| (pos, cell.intensity.strength))
+                .collect::<Vec<_>>()
+        };
+        let doubled: Vec<_> = food_strengths(SimulationConfig::default())
+            .into_iter()
+            .map(|(pos, strength)| (pos, strength * 2))
+            .collect();
+        assert!(!doubled.is_empty());
+        assert_eq!(food_strengths(config.clone()), doubled);
+
+        let mut game = init_game(10.0, 10.0, 10);
+        game.config = config;
+        let loaded = Game::load(game.snapshot().as_bytes()).unwrap();
+        assert_eq!(loaded.config, game.config);
+
+        // Built-in channels are set up from the config
+        let mut config = SimulationConfig::default();
+        config.set("sugar_boost", "3").unwrap();
+        config.set("repellent_evaporation_rate", "5").unwrap();
+        let game = GameBuilder::new(5.0, 5.0).config(config).build().unwrap();
+        let channels = &game.entity_store.pheromone_channels;
+        assert_eq!(
+            channels.get(PheromoneChannel::FOOD).boosts,
+            vec![(EntityType::Sugar, 3)]
+        );
+        assert_eq!(
+            channels.get(PheromoneChannel::REPELLENT).evaporation_rate,
+            5
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        use crate::replay::*;
+
+        let mut game = init_game(10.0, 10.0, 10);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        let mut recorder = Recorder::new(&game, true);
+        for tick in 0..10 {
+            // Something the replay doesn't know about
+            if tick == 5 {
+                game.entity_store
+                    .capacities
+                    .insert(ant_id, CapacityComponent { capacity: 3 });
+            }
+
+            game.tick();
+            recorder.record_tick(&game);
+        }
+
+        let replay = recorder.finish();
+        let mut buffer = vec![];
+        replay.write(&mut buffer).unwrap();
+        assert_eq!(Replay::read(buffer.as_slice()).unwrap(), replay);
+
+        let divergence = replay.check().unwrap().unwrap_err();
+        assert_eq!(divergence.tick, 5);
+        assert!(divergence.differences.contains(&Difference {
+            entity: Some(ant_id),
+            component: "capacity".to_owned(),
+            expected: Some(format!("capacity {ant_id}.0 3")),
+            actual: Some(format!("capacity {ant_id}.0 1")),
+        }));
+
+        let mut game = init_game(10.0, 10.0, 10);
+        let mut recorder = Recorder::new(&game, false);
+        for _ in 0..10 {
+            game.tick();
+            recorder.record_tick(&game);
+        }
+        assert_eq!(recorder.finish().check().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut game = init_game(5.0, 5.0, 1);
+        assert_eq!(game.entity_store.validate(), vec![]);
+
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        game.entity_store.walls.insert(ant_id, WallEntity {});
+        let wall_id = game.entity_store.create_entity(EntityType::Wall);
+        game.entity_store.destroy_entity(wall_id);
+        game.entity_store.sugars.insert(wall_id, SugarEntity {});
+
+        assert_eq!(
+            game.entity_store.validate(),
+            vec![
+                Violation::WrongEntityMaps {
+                    id: ant_id,
+                    entity_type: Some(EntityType::Ant),
+                    maps: vec![EntityType::Ant, EntityType::Wall],
+                },
+                Violation::StaleHandle { id: wall_id },
+                Violation::WrongEntityMaps {
+                    id: wall_id,
+                    entity_type: None,
+                    maps: vec![EntityType::Sugar],
+                },
+            ]
+        );
+
+        // Components and tandems of entities that don't exist
+        let mut game = init_game(5.0, 5.0, 1);
+        let ant_id = *game.entity_store.ants.keys().next().unwrap();
+        let missing_id: EntityIndex = "7.0".parse().unwrap();
+        game.entity_store.tandems.insert(
+            ant_id,
+            TandemComponent {
+                partner: missing_id,
+                role: TandemRole::Leader,
+                waited: 0,
+            },
+        );
+        game.entity_store.restore_slots(vec![0, 0, 0], vec![0]);
+        assert_eq!(
+            game.entity_store.validate(),
+            vec![
+                Violation::MissingEntity { id: missing_id },
+                Violation::FreeSlotInUse { id: ant_id },
+            ]
+        );
+
+        let mut game = init_game(5.0, 5.0, 1);
+        let sugar_id = *game.entity_store.sugars.keys().next().unwrap();
+        game.entity_store.edibles.get_mut(&sugar_id).unwrap().weight = 0;
+        assert_eq!(
+            game.entity_store.validate(),
+            vec![Violation::WeightlessEdible { id: sugar_id }]
+        );
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut game = init_game(10.0, 10.0, 10);
+        assert!(game.rewind(1).is_err());
+
+        game.keep_history(2, 10);
+        let events = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let subscriber_events = events.clone();
+        game.entity_store
+            .events
+            .subscribe(move |_| *subscriber_events.lock().unwrap() += 1);
+
+        let mut states = vec![game.snapshot()];
+        for _ in 0..25 {
+            game.tick();
+            states.push(game.snapshot());
+        }
+        let seen = *events.lock().unwrap();
+
+        // Only the snapshots of ticks 10 and 20 are kept
+        assert!(game.rewind(16).is_err());
+        game.rewind(12).unwrap();
+        assert_eq!(game.snapshot(), states[13]);
+        assert_eq!(*events.lock().unwrap(), seen);
+
+        for _ in 0..12 {
+            game.tick();
+        }
+        assert_eq!(game.snapshot(), states[25]);
+        game.rewind(5).unwrap();
+        assert_eq!(game.snapshot(), states[20]);
+    }
+
+    #[test]
+    fn test_query() {
+        let mut game = init_game(5.0, 5.0, 3);
+        let ant_ids: Vec<EntityIndex> = game.entity_store.ants.keys().copied().collect();
+        game.entity_store
+            .carrying_food
+            .insert(ant_ids[1], CarryingFoodComponent { amount: 1 });
+        game.entity_store
+            .builders
+            .insert(ant_ids[2], BuilderComponent {});
+
+        let carrying: Vec<EntityIndex> = game
+            .entity_store
+            .query()
+            .with::<AntEntity>()
+            .with::<CarryingFoodComponent>()
+            .iter()
+            .collect();
+        assert_eq!(carrying, vec![ant_ids[1]]);
+
+        let idle: Vec<EntityIndex> = game
+            .entity_store
+            .query()
+            .with::<AntEntity>()
+            .without::<CarryingFoodComponent>()
+            .without::<BuilderComponent>()
+            .iter()
+            .collect();
+        assert_eq!(idle, vec![ant_ids[0]]);
+
+        game.entity_store
+            .query_mut::<CapacityComponent>()
+            .without::<BuilderComponent>()
+            .for_each(|_, capacity| capacity.capacity = 3);
+        assert_eq!(
+            game.entity_store.get::<CapacityComponent>(ant_ids[1]),
+            Some(&CapacityComponent { capacity: 3 })
+        );
+        assert_eq!(
+            game.entity_store.get::<CapacityComponent>(ant_ids[2]),
+            Some(&CapacityComponent { capacity: 1 })
+        );
+    }
+
+    struct AntMovedSystem {
+        moved: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl System for AntMovedSystem {
+        fn name(&self) -> &'static str {
+            "ant_moved"
+        }
+
+        fn run(&mut self, game: &mut Game) {
+            let pos = PositionComponent::new(0.5, 2.5);
+            let ants_at_start = game
+                .entity_store
+                .get_entities_with_type_at(&pos, EntityType::Ant)
+                .count();
+            self.moved
+                .store(ants_at_start == 0, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let mut game = init_game(5.0, 5.0, 1);
+        let moved = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        game.scheduler.add_after(
+            "ants",
+            Box::new(AntMovedSystem {
+                moved: moved.clone(),
+            }),
+        );
+
+        game.scheduler.set_enabled("ants", false);
+        game.tick();
+        assert!(!moved.load(std::sync::atomic::Ordering::Relaxed));
+
+        game.scheduler.set_enabled("ants", true);
+        game.tick();
+        assert!(moved.load(std::sync::atomic::Ordering::Relaxed));
+
+        let names: Vec<&str> = game.scheduler.timings().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["pheromones", "ants", "ant_moved", "castes"]);
+    }
+
+    #[test]
+    fn test_movement_threads() {
+        let positions = |threads| {
+            let mut game = init_deneubourg_game(10.0, 10.0, 40);
+            game.set_seed(7);
+            game.movement_threads = threads;
+            for _ in 0..50 {
+                game.tick();
+            }
+
+            game.entity_store
+                .ants
+                .keys()
+                .map(|id| game.entity_store.get_position(*id).unwrap().clone())
+                .collect::<Vec<_>>()
+        };
+
+        let single_threaded = positions(None);
+        assert_eq!(single_threaded, positions(Some(1)));
+        assert_eq!(single_threaded, positions(Some(3)));
+        assert_eq!(single_threaded, positions(Some(8)));
+    }
+
+    #[test]
+    fn test_ant_rng_streams() {
+        let mut game = init_game(5.0, 5.0, 2);
+        let ant_ids: Vec<EntityIndex> = game.entity_store.ants.keys().copied().collect();
+        let first_draw = |game: &Game, ant_id| game.movement().ant_rng(ant_id).gen::<u64>();
+
+        // Neither the next seed nor the next tick repeat the stream of
+        // a neighbouring ant
+        let draw = first_draw(&game, ant_ids[1]);
+        game.set_seed(1);
+        assert_ne!(first_draw(&game, ant_ids[0]), draw);
+        game.set_seed(0);
+        game.entity_store.tick += 1;
+        assert_ne!(first_draw(&game, ant_ids[0]), draw);
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench`
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_thousands_of_ants() {
+        let mut game = init_game(100.0, 100.0, 0);
+        for i in 0..5000 {
+            let index = game.entity_store.create_entity(EntityType::Ant);
+            game.entity_store.update_position(
+                index,
+                &PositionComponent::new(0.5 + f64::from(i % 100), 0.5 + f64::from(i / 100)),
+            );
+        }
+
+        let start = std::time::Instant::now();
+        for _ in 0..50 {
+            game.tick();
+        }
+        let elapsed = start.elapsed();
+
+        println!(
+            "{} ants: {:?} per tick",
+            game.entity_store.ants.len(),
+            elapsed / 50
+        );
+    }
+
     #[test]
     fn test_10x10_open() {
         // let mut game = init_game(10.0, 10.0, 10);
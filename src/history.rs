@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+
+/// Saved states of the last ticks of a game, taken every `interval`
+/// ticks. Only the newest `depth` are kept.
+pub struct History {
+    depth: usize,
+    interval: u32,
+    snapshots: VecDeque<(u32, String)>,
+}
+
+impl History {
+    pub fn new(depth: usize, interval: u32) -> Self {
+        assert!(depth > 0, "History needs room for at least one snapshot!");
+        assert!(interval > 0, "History interval can't be 0!");
+
+        Self {
+            depth,
+            interval,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Calls `snapshot` if a snapshot of `tick` should be kept.
+    pub fn record<F: FnOnce() -> String>(&mut self, tick: u32, snapshot: F) {
+        let is_newer = self.snapshots.back().is_none_or(|(last, _)| *last < tick);
+        if is_newer && (self.snapshots.is_empty() || tick.is_multiple_of(self.interval)) {
+            self.snapshots.push_back((tick, snapshot()));
+
+            if self.snapshots.len() > self.depth {
+                self.snapshots.pop_front();
+            }
+        }
+    }
+
+    /// Drops the snapshots taken after `tick`.
+    pub fn forget_after(&mut self, tick: u32) {
+        while self.snapshots.back().is_some_and(|(last, _)| *last > tick) {
+            self.snapshots.pop_back();
+        }
+    }
+
+    /// The newest snapshot taken at or before `tick`.
+    pub fn latest_until(&self, tick: u32) -> Option<&(u32, String)> {
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|(taken, _)| *taken <= tick)
+    }
+}
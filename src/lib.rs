@@ -0,0 +1,46 @@
+#![deny(clippy::pedantic)]
+#![allow(
+    clippy::cast_sign_loss,
+    clippy::cast_possible_wrap,
+    clippy::cast_possible_truncation,
+    clippy::non_ascii_literal
+)]
+extern crate rand;
+
+pub mod builder;
+pub mod channels;
+pub mod components;
+pub mod config;
+pub mod coordinate;
+pub mod entities;
+pub mod entity_store;
+pub mod events;
+mod fields;
+pub mod game;
+mod history;
+mod query;
+pub mod replay;
+mod save;
+mod spatial;
+pub mod stop;
+mod storage;
+pub mod system;
+mod validation;
+
+pub use builder::{BuildError, GameBuilder};
+pub use channels::{
+    ChannelDeclaration, ChannelDeclarations, PheromoneChannel, PheromoneChannelSettings,
+    PheromoneChannels,
+};
+pub use config::SimulationConfig;
+pub use coordinate::Coordinate;
+pub use entities::EntityType;
+pub use entity_store::{EntityIndex, EntityStore};
+pub use events::Event;
+pub use fields::{PheromoneCell, PheromoneField};
+pub use game::{Game, Topology};
+pub use query::{Component, Query, QueryMut};
+pub use stop::{Stop, StopCondition};
+pub use storage::{AnyStorage, ComponentStorage, DenseStorage, SparseStorage};
+pub use system::System;
+pub use validation::Violation;
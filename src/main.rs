@@ -5,77 +5,548 @@
     clippy::cast_possible_truncation,
     clippy::non_ascii_literal
 )]
+extern crate ants;
 extern crate clap;
-extern crate rand;
-
-mod components;
-mod entities;
-mod entity_store;
-mod game;
-mod utils;
-
-use clap::{App, Arg};
-use components::*;
-use entities::*;
-use entity_store::*;
-use game::*;
-
-fn args() -> clap::ArgMatches<'static> {
-    App::new("ACO simulator")
+
+use ants::builder::GameBuilder;
+use ants::components::{Caste, CasteComponent};
+use ants::config::SimulationConfig;
+use ants::entity_store::EntityIndex;
+use ants::events::Event;
+use ants::game::Game;
+use ants::replay::{Recorder, Replay};
+use ants::stop::{Stop, StopCondition, StopConditions};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Exit code when a check finds a problem, like a diverged replay.
+const EXIT_CHECK_FAILED: i32 = 1;
+/// Exit code for input that can't be used, like a missing file or a bad flag.
+const EXIT_BAD_INPUT: i32 = 2;
+
+fn fail(code: i32, message: &str) -> ! {
+    eprintln!("error: {message}");
+    process::exit(code)
+}
+
+fn number<T: FromStr>(args: &ArgMatches, name: &str) -> Option<T> {
+    args.value_of(name).map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| fail(EXIT_BAD_INPUT, &format!("{name} should be a number")))
+    })
+}
+
+fn open(path: &str) -> BufReader<File> {
+    let file = File::open(path)
+        .unwrap_or_else(|error| fail(EXIT_BAD_INPUT, &format!("could not open {path}: {error}")));
+    BufReader::new(file)
+}
+
+fn create(path: &str) -> File {
+    File::create(path)
+        .unwrap_or_else(|error| fail(EXIT_BAD_INPUT, &format!("could not create {path}: {error}")))
+}
+
+/// Flags describing the game to play.
+fn game_args(app: App<'static, 'static>) -> App<'static, 'static> {
+    app.arg(
+        Arg::with_name("walls")
+            .short("w")
+            .long("walls")
+            .help("Add Deneubourg walls"),
+    )
+    .arg(
+        Arg::with_name("castes")
+            .short("c")
+            .long("castes")
+            .help("Divide ants into scouts, foragers and a reserve at the base"),
+    )
+    .arg(
+        Arg::with_name("tandem")
+            .short("t")
+            .long("tandem")
+            .help("Let informed ants lead naive ants to food"),
+    )
+    .arg(
+        Arg::with_name("seed")
+            .long("seed")
+            .takes_value(true)
+            .help("Seed for the random numbers of the simulation"),
+    )
+    .arg(
+        Arg::with_name("threads")
+            .long("threads")
+            .takes_value(true)
+            .help("Move ants on this many threads"),
+    )
+    .arg(
+        Arg::with_name("load")
+            .long("load")
+            .takes_value(true)
+            .help("Continue a saved game instead of starting a new one"),
+    )
+    .arg(
+        Arg::with_name("config")
+            .long("config")
+            .takes_value(true)
+            .conflicts_with("load")
+            .help("Read simulation parameters from a file of key = value lines"),
+    )
+}
+
+/// Flags deciding when the run stops, at the first one that is met.
+fn stop_args(app: App<'static, 'static>) -> App<'static, 'static> {
+    app.arg(
+        Arg::with_name("ticks")
+            .long("ticks")
+            .takes_value(true)
+            .default_value("300")
+            .help("Stop after this many ticks"),
+    )
+    .arg(
+        Arg::with_name("until-food")
+            .long("until-food")
+            .takes_value(true)
+            .help("Stop once the base holds this much food"),
+    )
+    .arg(
+        Arg::with_name("until-converged")
+            .long("until-converged")
+            .takes_value(true)
+            .help("Stop once the route hasn't changed for this many ticks"),
+    )
+    .arg(
+        Arg::with_name("timeout")
+            .long("timeout")
+            .takes_value(true)
+            .help("Stop after this many seconds"),
+    )
+}
+
+/// Flags for what to keep of a played game.
+fn output_args(app: App<'static, 'static>) -> App<'static, 'static> {
+    app.arg(
+        Arg::with_name("events")
+            .short("e")
+            .long("events")
+            .help("Print every simulation event"),
+    )
+    .arg(
+        Arg::with_name("save")
+            .long("save")
+            .takes_value(true)
+            .help("Save the game after the last tick"),
+    )
+    .arg(
+        Arg::with_name("record")
+            .long("record")
+            .takes_value(true)
+            .help("Record a replay of the run"),
+    )
+    .arg(
+        Arg::with_name("deltas")
+            .long("deltas")
+            .requires("record")
+            .help("Record what changed every tick, so divergences can be traced"),
+    )
+}
+
+fn play_args(name: &str, about: &'static str) -> App<'static, 'static> {
+    output_args(stop_args(game_args(
+        SubCommand::with_name(name).about(about),
+    )))
+}
+
+fn file_arg(help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("FILE").required(true).help(help)
+}
+
+fn args() -> ArgMatches<'static> {
+    let render = play_args("render", "Print the board every tick")
+        .arg(
+            Arg::with_name("timings")
+                .long("timings")
+                .help("Print how long every system took each tick"),
+        )
+        .arg(
+            Arg::with_name("rewind")
+                .long("rewind")
+                .takes_value(true)
+                .help("Step back this many ticks after the last one and print the game"),
+        );
+    let sweep = stop_args(game_args(
+        SubCommand::with_name("sweep").about("Run every combination of simulation parameters"),
+    ))
+    .arg(
+        Arg::with_name("param")
+            .long("param")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .required(true)
+            .conflicts_with("load")
+            .help("A parameter and the values to try, like pheromone_strength=8,16,32"),
+    )
+    .arg(
+        Arg::with_name("seeds")
+            .long("seeds")
+            .takes_value(true)
+            .default_value("1")
+            .help("Run every combination with this many seeds, counting up from --seed"),
+    );
+
+    let app = App::new("ACO simulator")
         .version("1.0")
         .author("Joren Van Onder <joren@jvo.sh>")
-        .arg(
-            Arg::with_name("walls")
-                .short("w")
-                .long("walls")
-                .help("Add Deneubourg walls"),
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(play_args(
+            "run",
+            "Run without printing the board and summarize",
+        ))
+        .subcommand(render)
+        .subcommand(sweep)
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("Re-run a recorded replay and report where it diverges")
+                .arg(file_arg("The recorded replay")),
         )
-        .get_matches()
+        .subcommand(game_args(SubCommand::with_name("validate").about(
+            "Check the game a run would start with, or a saved one with --load, without running it",
+        )));
+
+    app.get_matches_safe().unwrap_or_else(|error| {
+        if error.use_stderr() {
+            eprintln!("{}", error.message);
+            process::exit(EXIT_BAD_INPUT);
+        }
+        error.exit()
+    })
 }
 
-fn main() {
+/// A new game, with `params` applied on top of the `--config` file.
+fn new_game(args: &ArgMatches, params: &[(String, String)]) -> Game {
     const WIDTH: f64 = 10.0;
     const HEIGHT: f64 = 10.0;
 
-    let args = args();
-    let mut game = Game::init(EntityStore::default(), WIDTH, HEIGHT);
-    let mut index;
+    let with_castes = args.is_present("castes");
+    let caste = |i: u32| match i % 5 {
+        0 => Caste::Scout,
+        1 | 2 => Caste::Forager,
+        _ => Caste::Reserve,
+    };
 
+    let mut config = args
+        .value_of("config")
+        .map_or_else(SimulationConfig::default, |path| {
+            SimulationConfig::read(open(path)).unwrap_or_else(|error| {
+                fail(EXIT_BAD_INPUT, &format!("could not read config: {error}"))
+            })
+        });
+    for (key, value) in params {
+        config
+            .set(key, value)
+            .unwrap_or_else(|error| fail(EXIT_BAD_INPUT, &error));
+    }
+
+    let mut builder = GameBuilder::new(WIDTH, HEIGHT);
+    builder.config(config);
+    if let Some(seed) = number(args, "seed") {
+        builder.seed(seed);
+    }
     for i in 0..50 {
-        index = game.entity_store.create_entity(EntityType::Ant);
-        game.entity_store.update_position(
-            index,
-            &PositionComponent {
-                x: (0.5 + f64::from(i)) % WIDTH,
-                y: HEIGHT / 2.0,
-            },
-        );
+        let x = if with_castes && caste(i) == Caste::Reserve {
+            0.5
+        } else {
+            (0.5 + f64::from(i)) % WIDTH
+        };
+        builder.ant(x, HEIGHT / 2.0);
+    }
+    builder
+        .base(0.5, HEIGHT / 2.0)
+        .sugar(WIDTH - 0.5, HEIGHT / 2.0);
+    if args.is_present("walls") {
+        builder.deneubourg_walls();
     }
 
-    index = game.entity_store.create_entity(EntityType::Base);
-    game.entity_store.update_position(
-        index,
-        &PositionComponent {
-            x: 0.5,
-            y: HEIGHT / 2.0,
-        },
-    );
+    let mut game = builder
+        .build()
+        .unwrap_or_else(|error| fail(EXIT_BAD_INPUT, &format!("invalid scenario: {error}")));
+    game.scheduler
+        .set_enabled("tandem_runs", args.is_present("tandem"));
+    game.movement_threads = number(args, "threads");
 
-    index = game.entity_store.create_entity(EntityType::Sugar);
-    game.entity_store.update_position(
-        index,
-        &PositionComponent {
-            x: WIDTH - 0.5,
-            y: HEIGHT / 2.0,
-        },
-    );
+    if with_castes {
+        let ant_ids: Vec<EntityIndex> = game.entity_store.ants.keys().copied().collect();
+        for (i, ant_id) in (0..).zip(ant_ids) {
+            game.entity_store.castes.insert(
+                ant_id,
+                CasteComponent {
+                    caste: caste(i),
+                    threshold: 1 + i % 4,
+                },
+            );
+        }
+    }
 
-    if args.is_present("walls") {
-        game.add_deneubourg_walls();
+    game
+}
+
+fn load_game(path: &str) -> Game {
+    Game::load(open(path))
+        .unwrap_or_else(|error| fail(EXIT_BAD_INPUT, &format!("could not load {path}: {error}")))
+}
+
+fn game(args: &ArgMatches, params: &[(String, String)]) -> Game {
+    let mut game = match args.value_of("load") {
+        Some(path) => load_game(path),
+        None => new_game(args, params),
+    };
+
+    if args.is_present("events") {
+        game.entity_store
+            .events
+            .subscribe(|event| println!("{event:?}"));
+    }
+
+    game
+}
+
+fn stop_conditions(args: &ArgMatches) -> Vec<StopCondition> {
+    let mut conditions = vec![];
+    if let Some(ticks) = number(args, "ticks") {
+        conditions.push(StopCondition::Ticks(ticks));
     }
+    if let Some(food) = number(args, "until-food") {
+        conditions.push(StopCondition::FoodInBase(food));
+    }
+    if let Some(ticks) = number(args, "until-converged") {
+        conditions.push(StopCondition::Converged(ticks));
+    }
+    if let Some(seconds) = number(args, "timeout") {
+        conditions.push(StopCondition::Timeout(Duration::from_secs(seconds)));
+    }
+    conditions
+}
 
-    for i in 0..300 {
-        println!("Tick #{}\n{}", i, game);
+/// Ticks until a stop condition is met, printing the board every
+/// tick if `render` is set.
+fn play(args: &ArgMatches, game: &mut Game, render: bool) -> Stop {
+    let mut recorder = args
+        .value_of("record")
+        .map(|_| Recorder::new(game, args.is_present("deltas")));
+
+    let mut stop_conditions = StopConditions::new(stop_conditions(args))
+        .unwrap_or_else(|error| fail(EXIT_BAD_INPUT, &error));
+    let mut i = 0;
+    let stop = loop {
+        if let Some(stop) = stop_conditions.check(game) {
+            break stop;
+        }
+
+        if render {
+            println!("Tick #{i}\n{game}");
+        }
         game.tick();
+
+        if let Some(recorder) = &mut recorder {
+            recorder.record_tick(game);
+        }
+
+        if render {
+            for event in game.entity_store.events.drain() {
+                if let Event::FoodDelivered { ant, amount } = event {
+                    println!("ant {ant} delivered {amount} food!");
+                }
+            }
+        }
+
+        if args.is_present("timings") {
+            for (name, duration) in game.scheduler.timings() {
+                println!("{name}: {duration:?}");
+            }
+        }
+        i += 1;
+    };
+
+    if let (Some(recorder), Some(path)) = (recorder, args.value_of("record")) {
+        recorder
+            .finish()
+            .write(&mut create(path))
+            .unwrap_or_else(|error| {
+                fail(EXIT_BAD_INPUT, &format!("could not write replay: {error}"))
+            });
+    }
+
+    stop
+}
+
+fn save(args: &ArgMatches, game: &Game) {
+    if let Some(path) = args.value_of("save") {
+        game.save(&mut create(path)).unwrap_or_else(|error| {
+            fail(EXIT_BAD_INPUT, &format!("could not write save: {error}"))
+        });
+    }
+}
+
+fn run(args: &ArgMatches) {
+    let mut game = game(args, &[]);
+    let started = Instant::now();
+    let stop = play(args, &mut game, false);
+
+    println!("Stopped after tick {}: {}", stop.tick, stop.condition);
+    println!("food in base: {}", game.entity_store.food_in_base);
+    println!(
+        "delivery rate: {} per {} ticks",
+        game.delivery_rate(),
+        game.config.delivery_rate_window
+    );
+    println!("ants: {}", game.entity_store.ants.len());
+    println!("sugar sources: {}", game.entity_store.sugars.len());
+    println!("took: {:?}", started.elapsed());
+    save(args, &game);
+}
+
+fn render(args: &ArgMatches) {
+    let mut game = game(args, &[]);
+    print!("{}", game.config);
+
+    let rewind: Option<u32> = number(args, "rewind");
+    if let Some(ticks) = rewind {
+        const INTERVAL: u32 = 10;
+        game.keep_history((ticks / INTERVAL) as usize + 2, INTERVAL);
+    }
+
+    let stop = play(args, &mut game, true);
+    println!("Stopped after tick {}: {}", stop.tick, stop.condition);
+
+    if let Some(ticks) = rewind {
+        game.rewind(ticks)
+            .unwrap_or_else(|error| fail(EXIT_BAD_INPUT, &format!("could not rewind: {error}")));
+        println!("Rewound {ticks} ticks\n{game}");
+    }
+
+    save(args, &game);
+}
+
+/// Every combination of the `--param key=value,value` flags.
+fn parameter_grid(args: &ArgMatches) -> Vec<Vec<(String, String)>> {
+    let mut grid = vec![vec![]];
+    for param in args.values_of("param").into_iter().flatten() {
+        let (key, values) = param.split_once('=').unwrap_or_else(|| {
+            fail(
+                EXIT_BAD_INPUT,
+                &format!("{param} should look like key=value,value"),
+            )
+        });
+        for value in values.split(',') {
+            if let Err(error) = SimulationConfig::default().set(key, value) {
+                fail(EXIT_BAD_INPUT, &error);
+            }
+        }
+
+        grid = grid
+            .iter()
+            .flat_map(|combination| {
+                values.split(',').map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((key.to_owned(), value.to_owned()));
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    grid
+}
+
+fn sweep(args: &ArgMatches) {
+    let first_seed = number(args, "seed").unwrap_or(0);
+    let seeds: u64 = number(args, "seeds").unwrap_or(1);
+    for combination in parameter_grid(args) {
+        for seed in first_seed..first_seed + seeds {
+            // The pheromone channels are set up from the parameters
+            // when the game is built
+            let mut game = game(args, &combination);
+            game.set_seed(seed);
+
+            let stop = play(args, &mut game, false);
+            let params: Vec<String> = combination
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect();
+            println!(
+                "{} seed={} tick={} food={} ({})",
+                params.join(" "),
+                seed,
+                stop.tick,
+                game.entity_store.food_in_base,
+                stop.condition
+            );
+        }
+    }
+}
+
+fn replay(args: &ArgMatches) {
+    let path = args.value_of("FILE").unwrap();
+    let replay = Replay::read(open(path))
+        .unwrap_or_else(|error| fail(EXIT_BAD_INPUT, &format!("could not read replay: {error}")));
+    let result = replay.check().unwrap_or_else(|error| {
+        fail(
+            EXIT_BAD_INPUT,
+            &format!("could not load the initial state: {error}"),
+        )
+    });
+    match result {
+        Ok(()) => println!("replay of {} ticks matches", replay.ticks.len()),
+        Err(divergence) => {
+            print!("{divergence}");
+            process::exit(EXIT_CHECK_FAILED);
+        }
+    }
+}
+
+/// Games that can't be loaded or built exit like any other bad input,
+/// only inconsistencies in a loaded store are reported as a failed check.
+fn validate(args: &ArgMatches) {
+    let game = game(args, &[]);
+    let name = args
+        .value_of("load")
+        .or_else(|| args.value_of("config"))
+        .unwrap_or("the scenario");
+
+    let violations = game.entity_store.validate();
+    if !violations.is_empty() {
+        println!("{name} is invalid:");
+        for violation in violations {
+            println!("{violation}");
+        }
+        process::exit(EXIT_CHECK_FAILED);
+    }
+
+    println!(
+        "{} is valid: {}x{} with {} ants, {} sugar sources and {} walls",
+        name,
+        game.width(),
+        game.height(),
+        game.entity_store.ants.len(),
+        game.entity_store.sugars.len(),
+        game.entity_store.walls.len()
+    );
+}
+
+fn main() {
+    let args = args();
+    match args.subcommand() {
+        ("run", Some(args)) => run(args),
+        ("render", Some(args)) => render(args),
+        ("sweep", Some(args)) => sweep(args),
+        ("replay", Some(args)) => replay(args),
+        ("validate", Some(args)) => validate(args),
+        _ => unreachable!("clap requires a subcommand"),
     }
 }
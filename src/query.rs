@@ -0,0 +1,195 @@
+use crate::components::{
+    AdventurousComponent, BuilderComponent, CapacityComponent, CarryingFoodComponent,
+    CasteComponent, EdibleComponent, ImpenetrableComponent, KnownFoodComponent,
+    ReleasingPheromoneComponent, ShortMemory, TandemComponent,
+};
+use crate::entities::{AntEntity, BaseEntity, SugarEntity, WallEntity};
+use crate::entity_store::{EntityIndex, EntityStore};
+use crate::storage::{AnyStorage, ComponentStorage, DenseStorage, SparseStorage};
+
+/// A type that is stored in one of the `EntityStore` storages.
+pub trait Component: Sized + 'static {
+    type Storage: ComponentStorage<Self>;
+
+    fn storage(store: &EntityStore) -> &Self::Storage;
+    fn storage_mut(store: &mut EntityStore) -> &mut Self::Storage;
+}
+
+macro_rules! component {
+    ($type:ty, $field:ident, $storage:ident) => {
+        impl Component for $type {
+            type Storage = $storage<Self>;
+
+            fn storage(store: &EntityStore) -> &Self::Storage {
+                &store.$field
+            }
+
+            fn storage_mut(store: &mut EntityStore) -> &mut Self::Storage {
+                &mut store.$field
+            }
+        }
+    };
+}
+
+component!(AntEntity, ants, SparseStorage);
+component!(SugarEntity, sugars, SparseStorage);
+component!(BaseEntity, bases, SparseStorage);
+component!(WallEntity, walls, SparseStorage);
+component!(EdibleComponent, edibles, SparseStorage);
+component!(
+    ReleasingPheromoneComponent,
+    releasing_pheromones,
+    DenseStorage
+);
+component!(CarryingFoodComponent, carrying_food, SparseStorage);
+component!(CapacityComponent, capacities, DenseStorage);
+component!(BuilderComponent, builders, SparseStorage);
+component!(ImpenetrableComponent, impenetrables, SparseStorage);
+component!(ShortMemory, memories, DenseStorage);
+component!(AdventurousComponent, adventurous, SparseStorage);
+component!(CasteComponent, castes, SparseStorage);
+component!(TandemComponent, tandems, SparseStorage);
+component!(KnownFoodComponent, known_food, SparseStorage);
+
+/// Entities that have all `with` components and none of the
+/// `without` components. The smallest `with` storage drives the join.
+pub struct Query<'a> {
+    store: &'a EntityStore,
+    with: Vec<&'a dyn AnyStorage>,
+    without: Vec<&'a dyn AnyStorage>,
+}
+
+impl Query<'_> {
+    #[must_use]
+    pub fn with<C: Component>(mut self) -> Self {
+        self.with.push(C::storage(self.store));
+        self
+    }
+
+    #[must_use]
+    pub fn without<C: Component>(mut self) -> Self {
+        self.without.push(C::storage(self.store));
+        self
+    }
+
+    /// Matching entities in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = EntityIndex> + '_ {
+        let driver: &dyn AnyStorage = self
+            .with
+            .iter()
+            .min_by_key(|set| set.len())
+            .copied()
+            .unwrap_or(&self.store.entity_types);
+
+        driver.ids().filter(move |id| {
+            self.with.iter().all(|set| set.contains_key(id))
+                && !self.without.iter().any(|set| set.contains_key(id))
+        })
+    }
+}
+
+type Filter<'a> = Box<dyn Fn(&EntityStore, EntityIndex) -> bool + 'a>;
+
+/// Like `Query`, but hands out mutable access to component `C` of
+/// every matching entity.
+pub struct QueryMut<'a, C: Component> {
+    store: &'a mut EntityStore,
+    with: Vec<fn(&EntityStore) -> &dyn AnyStorage>,
+    without: Vec<fn(&EntityStore) -> &dyn AnyStorage>,
+    filters: Vec<Filter<'a>>,
+    component: std::marker::PhantomData<C>,
+}
+
+fn any_storage<C: Component>(store: &EntityStore) -> &dyn AnyStorage {
+    C::storage(store)
+}
+
+impl<'a, C: Component> QueryMut<'a, C> {
+    #[must_use]
+    pub fn with<W: Component>(mut self) -> Self {
+        self.with.push(any_storage::<W>);
+        self
+    }
+
+    #[must_use]
+    pub fn without<W: Component>(mut self) -> Self {
+        self.without.push(any_storage::<W>);
+        self
+    }
+
+    /// Only visits entities for which `filter` holds. Filters can read
+    /// the whole store because they run before anything is mutated.
+    #[must_use]
+    pub fn filter<F: Fn(&EntityStore, EntityIndex) -> bool + 'a>(mut self, filter: F) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Calls `f` with every matching entity and its component.
+    ///
+    /// # Panics
+    ///
+    /// Never, only entities with a `C` component match.
+    pub fn for_each<F: FnMut(EntityIndex, &mut C)>(self, mut f: F) {
+        let ids = {
+            let store: &EntityStore = self.store;
+            let mut query = store.query().with::<C>();
+            query.with.extend(self.with.iter().map(|set| set(store)));
+            query
+                .without
+                .extend(self.without.iter().map(|set| set(store)));
+            query
+                .iter()
+                .filter(|id| self.filters.iter().all(|filter| filter(store, *id)))
+                .collect::<Vec<_>>()
+        };
+
+        let storage = C::storage_mut(self.store);
+        for id in ids {
+            f(id, storage.get_mut(&id).unwrap());
+        }
+    }
+}
+
+impl EntityStore {
+    #[must_use]
+    pub fn query(&self) -> Query<'_> {
+        Query {
+            store: self,
+            with: vec![],
+            without: vec![],
+        }
+    }
+
+    pub fn query_mut<C: Component>(&mut self) -> QueryMut<'_, C> {
+        QueryMut {
+            store: self,
+            with: vec![],
+            without: vec![],
+            filters: vec![],
+            component: std::marker::PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub fn get<C: Component>(&self, id: EntityIndex) -> Option<&C> {
+        C::storage(self).get(&id)
+    }
+
+    pub fn get_mut<C: Component>(&mut self, id: EntityIndex) -> Option<&mut C> {
+        C::storage_mut(self).get_mut(&id)
+    }
+
+    pub fn add_component<C: Component>(&mut self, id: EntityIndex, component: C) -> Option<C> {
+        C::storage_mut(self).insert(id, component)
+    }
+
+    pub fn remove_component<C: Component>(&mut self, id: EntityIndex) -> Option<C> {
+        C::storage_mut(self).remove(&id)
+    }
+
+    #[must_use]
+    pub fn has<C: Component>(&self, id: EntityIndex) -> bool {
+        C::storage(self).contains_key(&id)
+    }
+}
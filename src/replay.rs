@@ -0,0 +1,291 @@
+use crate::entity_store::EntityIndex;
+use crate::game::Game;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+const HEADER: &str = "ants replay 1";
+
+/// FNV-1a, stable across Rust versions unlike `DefaultHasher`.
+fn hash(state: &str) -> u64 {
+    state.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn lines(state: &str) -> BTreeSet<String> {
+    state.lines().map(str::to_owned).collect()
+}
+
+/// Saved lines that changed during a tick.
+#[derive(Debug, Default, PartialEq)]
+pub struct Delta {
+    pub added: BTreeSet<String>,
+    pub removed: BTreeSet<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TickRecord {
+    pub hash: u64,
+    pub delta: Option<Delta>,
+}
+
+/// A run recorded as its initial state and what happened every tick.
+#[derive(Debug, PartialEq)]
+pub struct Replay {
+    pub initial: String,
+    pub ticks: Vec<TickRecord>,
+}
+
+pub struct Recorder {
+    replay: Replay,
+    previous: BTreeSet<String>,
+    record_deltas: bool,
+}
+
+impl Recorder {
+    /// Starts recording `game`. With `record_deltas` a divergence can
+    /// be traced to single entities and components.
+    #[must_use]
+    pub fn new(game: &Game, record_deltas: bool) -> Self {
+        let initial = game.snapshot();
+        Self {
+            previous: lines(&initial),
+            replay: Replay {
+                initial,
+                ticks: vec![],
+            },
+            record_deltas,
+        }
+    }
+
+    /// Records the state of `game` after a tick.
+    pub fn record_tick(&mut self, game: &Game) {
+        let state = game.snapshot();
+        let delta = if self.record_deltas {
+            let current = lines(&state);
+            let delta = Delta {
+                added: current.difference(&self.previous).cloned().collect(),
+                removed: self.previous.difference(&current).cloned().collect(),
+            };
+            self.previous = current;
+            Some(delta)
+        } else {
+            None
+        };
+
+        self.replay.ticks.push(TickRecord {
+            hash: hash(&state),
+            delta,
+        });
+    }
+
+    #[must_use]
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+/// One saved line that differs between the recording and the replay.
+#[derive(Debug, PartialEq)]
+pub struct Difference {
+    pub entity: Option<EntityIndex>,
+    pub component: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+/// First tick where a replay didn't match its recording.
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    pub tick: usize,
+    /// Empty if the recording has no deltas.
+    pub differences: Vec<Difference>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "diverged after tick {}", self.tick)?;
+        for difference in &self.differences {
+            match difference.entity {
+                Some(entity) => write!(f, "entity {} {}: ", entity, difference.component)?,
+                None => write!(f, "{}: ", difference.component)?,
+            }
+            writeln!(
+                f,
+                "expected {}, got {}",
+                difference.expected.as_deref().unwrap_or("nothing"),
+                difference.actual.as_deref().unwrap_or("nothing")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Pairs up lines of the same entity and component, so a changed
+/// value shows up as one difference.
+fn differences(expected: &BTreeSet<String>, actual: &BTreeSet<String>) -> Vec<Difference> {
+    let key = |line: &str| -> (String, Option<EntityIndex>) {
+        let mut values = line.split_whitespace();
+        let component = values.next().unwrap_or("").to_owned();
+        let second = values.next().unwrap_or("");
+        match second.parse() {
+            Ok(entity) => (component, Some(entity)),
+            Err(_) => (format!("{component} {second}"), None),
+        }
+    };
+
+    let mut differences: Vec<Difference> = vec![];
+    for line in expected.difference(actual) {
+        let (component, entity) = key(line);
+        differences.push(Difference {
+            entity,
+            component,
+            expected: Some(line.clone()),
+            actual: None,
+        });
+    }
+
+    for line in actual.difference(expected) {
+        let (component, entity) = key(line);
+        match differences
+            .iter_mut()
+            .find(|d| d.entity.is_some() && d.entity == entity && d.component == component)
+        {
+            Some(difference) if difference.actual.is_none() => {
+                difference.actual = Some(line.clone());
+            }
+            _ => differences.push(Difference {
+                entity,
+                component,
+                expected: None,
+                actual: Some(line.clone()),
+            }),
+        }
+    }
+
+    differences
+}
+
+impl Replay {
+    /// Re-runs the recording and returns where it first diverges.
+    ///
+    /// # Errors
+    ///
+    /// If the initial state isn't a valid save.
+    pub fn check(&self) -> io::Result<Result<(), Divergence>> {
+        let mut game = Game::load(self.initial.as_bytes())?;
+        let mut expected = lines(&self.initial);
+
+        for (tick, record) in self.ticks.iter().enumerate() {
+            game.tick();
+            let state = game.snapshot();
+
+            if let Some(delta) = &record.delta {
+                expected = expected.difference(&delta.removed).cloned().collect();
+                expected.extend(delta.added.iter().cloned());
+            }
+
+            if hash(&state) != record.hash {
+                let differences = if record.delta.is_some() {
+                    differences(&expected, &lines(&state))
+                } else {
+                    vec![]
+                };
+
+                return Ok(Err(Divergence { tick, differences }));
+            }
+        }
+
+        Ok(Ok(()))
+    }
+
+    /// # Errors
+    ///
+    /// If writing to `w` fails.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{HEADER}")?;
+        for line in self.initial.lines() {
+            writeln!(w, "= {line}")?;
+        }
+
+        for record in &self.ticks {
+            if let Some(delta) = &record.delta {
+                writeln!(w, "tick {} delta", record.hash)?;
+                for line in &delta.removed {
+                    writeln!(w, "- {line}")?;
+                }
+                for line in &delta.added {
+                    writeln!(w, "+ {line}")?;
+                }
+            } else {
+                writeln!(w, "tick {}", record.hash)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a replay written by `write`.
+    ///
+    /// # Errors
+    ///
+    /// If reading fails, or with `InvalidData` if it isn't a replay.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut lines = reader.lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid("Not a replay".to_owned()));
+        }
+
+        let mut replay = Replay {
+            initial: String::new(),
+            ticks: vec![],
+        };
+        for line in lines {
+            let line = line?;
+            let (kind, value) = line.split_at(line.find(' ').unwrap_or(line.len()));
+            let value = value.get(1..).unwrap_or("");
+
+            match (kind, replay.ticks.last_mut()) {
+                ("=", _) => {
+                    replay.initial += value;
+                    replay.initial.push('\n');
+                }
+                ("tick", _) => {
+                    let (hash, delta) = match value.split_once(' ') {
+                        Some((hash, "delta")) => (hash, Some(Delta::default())),
+                        _ => (value, None),
+                    };
+                    replay.ticks.push(TickRecord {
+                        hash: hash
+                            .parse()
+                            .map_err(|_| invalid(format!("Invalid line: {line}")))?,
+                        delta,
+                    });
+                }
+                (
+                    "-",
+                    Some(TickRecord {
+                        delta: Some(delta), ..
+                    }),
+                ) => {
+                    delta.removed.insert(value.to_owned());
+                }
+                (
+                    "+",
+                    Some(TickRecord {
+                        delta: Some(delta), ..
+                    }),
+                ) => {
+                    delta.added.insert(value.to_owned());
+                }
+                _ => return Err(invalid(format!("Invalid line: {line}"))),
+            }
+        }
+
+        Ok(replay)
+    }
+}
@@ -0,0 +1,623 @@
+use crate::channels::{PheromoneChannel, PheromoneChannelSettings, PheromoneChannels};
+use crate::components::{
+    AdventurousComponent, BuilderComponent, CapacityComponent, CarryingFoodComponent, Caste,
+    CasteComponent, CoarsePositionComponent, DirectionComponent, EdibleComponent,
+    ImpenetrableComponent, KnownFoodComponent, PositionComponent, ReleasingPheromoneComponent,
+    ShortMemory, TandemComponent, TandemRole,
+};
+use crate::config::SimulationConfig;
+use crate::entities::EntityType;
+use crate::entity_store::{EntityIndex, EntityStore};
+use crate::game::{Game, Topology};
+use colored::Color;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+const HEADER: &str = "ants save 3";
+
+const COLORS: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+    Color::BrightBlack,
+    Color::BrightRed,
+    Color::BrightGreen,
+    Color::BrightYellow,
+    Color::BrightBlue,
+    Color::BrightMagenta,
+    Color::BrightCyan,
+    Color::BrightWhite,
+];
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn id(id: EntityIndex) -> String {
+    format!("{}.{}", id.index(), id.generation())
+}
+
+fn list<T: ToString>(items: impl Iterator<Item = T>) -> String {
+    let items: Vec<String> = items.map(|item| item.to_string()).collect();
+    if items.is_empty() {
+        "-".to_owned()
+    } else {
+        items.join(",")
+    }
+}
+
+fn parse<T: FromStr>(value: Option<&str>, line: &str) -> io::Result<T> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| invalid(format!("Invalid line: {line}")))
+}
+
+fn parse_list<T: FromStr>(value: Option<&str>, line: &str) -> io::Result<Vec<T>> {
+    match value {
+        Some("-") => Ok(vec![]),
+        Some(value) => value
+            .split(',')
+            .map(|item| parse(Some(item), line))
+            .collect(),
+        None => Err(invalid(format!("Invalid line: {line}"))),
+    }
+}
+
+fn parse_entity_type(value: &str) -> Option<EntityType> {
+    match value {
+        "Ant" => Some(EntityType::Ant),
+        "Base" => Some(EntityType::Base),
+        "Sugar" => Some(EntityType::Sugar),
+        "Wall" => Some(EntityType::Wall),
+        _ => None,
+    }
+}
+
+fn parse_channel(
+    channels: &PheromoneChannels,
+    value: Option<&str>,
+    line: &str,
+) -> io::Result<PheromoneChannel> {
+    value
+        .and_then(|name| channels.find(name))
+        .ok_or_else(|| invalid(format!("Unknown pheromone channel: {line}")))
+}
+
+fn parse_memory(queue: Option<&str>, line: &str) -> io::Result<ShortMemory> {
+    let pos_queue = parse_list::<String>(queue, line)?
+        .iter()
+        .map(|pos| {
+            let (x, y) = pos.split_once(':')?;
+            Some(CoarsePositionComponent {
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+            })
+        })
+        .collect::<Option<VecDeque<_>>>()
+        .ok_or_else(|| invalid(format!("Invalid memory: {line}")))?;
+
+    Ok(ShortMemory {
+        pos: pos_queue.iter().cloned().collect(),
+        pos_queue,
+    })
+}
+
+fn parse_tandem<'a, I: Iterator<Item = &'a str>>(
+    values: &mut I,
+    line: &str,
+) -> io::Result<TandemComponent> {
+    let partner = parse(values.next(), line)?;
+    let role = match values.next() {
+        Some("Leader") => TandemRole::Leader,
+        Some("Follower") => TandemRole::Follower,
+        _ => return Err(invalid(format!("Invalid tandem role: {line}"))),
+    };
+
+    Ok(TandemComponent {
+        partner,
+        role,
+        waited: parse(values.next(), line)?,
+    })
+}
+
+impl Game {
+    /// Writes everything needed to continue the game later. Custom
+    /// systems and event subscribers aren't saved.
+    ///
+    /// # Errors
+    ///
+    /// If writing to `w` fails.
+    pub fn save<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let store = &self.entity_store;
+
+        writeln!(w, "{HEADER}")?;
+        writeln!(
+            w,
+            "size {} {} {}",
+            self.width(),
+            self.height(),
+            store.is_bounded()
+        )?;
+        writeln!(w, "seed {}", self.seed())?;
+        if let Some(threads) = self.movement_threads {
+            writeln!(w, "movement_threads {threads}")?;
+        }
+        if self.topology == Topology::Torus {
+            writeln!(w, "topology torus")?;
+        }
+        for (key, value) in self.config.values() {
+            writeln!(w, "config {key} {value}")?;
+        }
+        for (name, enabled) in self.scheduler.systems() {
+            writeln!(w, "system {name} {enabled}")?;
+        }
+
+        writeln!(w, "pheromone_generation {}", store.pheromone_generation)?;
+        writeln!(w, "tick {}", store.tick)?;
+        writeln!(w, "food_in_base {}", store.food_in_base)?;
+        writeln!(
+            w,
+            "recent_deliveries {}",
+            list(store.recent_deliveries.iter())
+        )?;
+        let (generations, free_slots) = store.slots();
+        writeln!(
+            w,
+            "slots {} {}",
+            list(generations.iter()),
+            list(free_slots.iter())
+        )?;
+
+        for (_, settings) in store.pheromone_channels.iter() {
+            writeln!(
+                w,
+                "channel {} {} {} {} {}",
+                settings.name,
+                settings.evaporation_rate,
+                settings.color.to_fg_str(),
+                list(
+                    settings
+                        .evaporation_exempt_at
+                        .iter()
+                        .map(|entity_type| format!("{entity_type:?}"))
+                ),
+                list(
+                    settings
+                        .boosts
+                        .iter()
+                        .map(|(entity_type, boost)| format!("{entity_type:?}:{boost}"))
+                ),
+            )?;
+        }
+
+        for (ph_channel, settings) in store.pheromone_channels.iter() {
+            if let Some(field) = store.pheromone_fields.get(ph_channel.index()) {
+                for (pos, cell) in field.iter() {
+                    writeln!(
+                        w,
+                        "pheromone {} {} {} {} {}",
+                        settings.name,
+                        pos.x,
+                        pos.y,
+                        cell.intensity.strength,
+                        cell.generation.generation
+                    )?;
+                }
+            }
+        }
+
+        for (entity_id, entity_type) in store.entity_types.iter() {
+            writeln!(w, "entity {} {:?}", id(*entity_id), entity_type)?;
+
+            if let Some(pos) = store.get_position(*entity_id) {
+                write!(w, "position {} {} {}", id(*entity_id), pos.x, pos.y)?;
+                if let Some(direction) = store.get_direction(*entity_id) {
+                    write!(w, " {} {}", direction.x, direction.y)?;
+                }
+                writeln!(w)?;
+            }
+        }
+
+        save_components(store, w)
+    }
+
+    /// The saved game as a string.
+    ///
+    /// # Panics
+    ///
+    /// Never, writing to memory can't fail.
+    #[must_use]
+    pub fn snapshot(&self) -> String {
+        let mut buffer = vec![];
+        self.save(&mut buffer)
+            .expect("writing to memory can't fail");
+        String::from_utf8(buffer).expect("saves are valid UTF-8")
+    }
+
+    /// Replaces the state of the game with a saved one. Custom systems
+    /// and event subscribers are kept.
+    ///
+    /// # Errors
+    ///
+    /// With `InvalidData` if `saved` isn't a valid save or the saved
+    /// game has a different size. The game is left as it was.
+    pub fn restore(&mut self, saved: &str) -> io::Result<()> {
+        let loaded = Game::load(saved.as_bytes())?;
+        let same_size = (loaded.width() - self.width()).abs() < f64::EPSILON
+            && (loaded.height() - self.height()).abs() < f64::EPSILON;
+        if !same_size {
+            return Err(invalid("Saved game has a different size".to_owned()));
+        }
+
+        self.set_seed(loaded.seed());
+        self.movement_threads = loaded.movement_threads;
+        self.topology = loaded.topology;
+        self.config = loaded.config.clone();
+        for (name, enabled) in loaded.scheduler.systems() {
+            self.scheduler.set_enabled(name, enabled);
+        }
+
+        let mut events = std::mem::take(&mut self.entity_store.events);
+        events.clear();
+        self.entity_store = loaded.entity_store;
+        self.entity_store.events = events;
+
+        Ok(())
+    }
+
+    /// Reads a game written by `save`.
+    ///
+    /// # Errors
+    ///
+    /// If reading fails, or with `InvalidData` if it isn't a valid save.
+    pub fn load<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid("Not a saved game".to_owned()));
+        }
+
+        let size_line = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| invalid("Missing size".to_owned()))?;
+        let mut size = size_line.split_whitespace().skip(1);
+        let width: f64 = parse(size.next(), &size_line)?;
+        let height: f64 = parse(size.next(), &size_line)?;
+        let is_size = |value: f64| value.is_finite() && value > 0.0;
+        if !is_size(width) || !is_size(height) {
+            return Err(invalid(format!("Invalid size: {size_line}")));
+        }
+        let mut store = if parse(size.next(), &size_line)? {
+            EntityStore::bounded(width, height)
+        } else {
+            EntityStore::default()
+        };
+        store.pheromone_channels = PheromoneChannels::new();
+
+        let mut game = Game::init(store, width, height, SimulationConfig::default());
+        for line in lines {
+            let line = line?;
+            game.load_line(&line)?;
+        }
+
+        Ok(game)
+    }
+
+    fn load_line(&mut self, line: &str) -> io::Result<()> {
+        let mut values = line.split_whitespace();
+        match values.next().unwrap_or("") {
+            "seed" => self.set_seed(parse(values.next(), line)?),
+            "movement_threads" => self.movement_threads = Some(parse(values.next(), line)?),
+            "topology" => {
+                self.topology = match values.next() {
+                    Some("bounded") => Topology::Bounded,
+                    Some("torus") => Topology::Torus,
+                    _ => return Err(invalid(format!("Invalid topology: {line}"))),
+                }
+            }
+            "config" => {
+                let key = values.next().unwrap_or("");
+                // Declared channels are separated by spaces
+                let value = values.collect::<Vec<_>>().join(" ");
+                self.config
+                    .set(key, &value)
+                    .map_err(|error| invalid(format!("{error}: {line}")))?;
+            }
+            "system" => {
+                let name = values.next().unwrap_or("");
+                let enabled = parse(values.next(), line)?;
+                // Custom systems aren't saved, the game they're added to
+                // decides whether they run
+                if self.scheduler.systems().any(|(system, _)| system == name) {
+                    self.scheduler.set_enabled(name, enabled);
+                }
+            }
+            _ => load_store_line(&mut self.entity_store, line)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn load_store_line(store: &mut EntityStore, line: &str) -> io::Result<()> {
+    let mut values = line.split_whitespace();
+    match values.next().unwrap_or("") {
+        "" => {}
+        "pheromone_generation" => store.pheromone_generation = parse(values.next(), line)?,
+        "tick" => store.tick = parse(values.next(), line)?,
+        "food_in_base" => store.food_in_base = parse(values.next(), line)?,
+        "recent_deliveries" => {
+            store.recent_deliveries = parse_list(values.next(), line)?.into_iter().collect();
+        }
+        "slots" => {
+            let generations = parse_list(values.next(), line)?;
+            let free_slots: Vec<usize> = parse_list(values.next(), line)?;
+            if free_slots.iter().any(|slot| *slot >= generations.len()) {
+                return Err(invalid(format!("Invalid free slot: {line}")));
+            }
+            store.restore_slots(generations, free_slots);
+        }
+        "channel" => {
+            let mut settings = PheromoneChannelSettings::new(values.next().unwrap_or(""));
+            settings.evaporation_rate = parse(values.next(), line)?;
+            let color = values.next();
+            settings.color = COLORS
+                .iter()
+                .copied()
+                .find(|color_option| Some(color_option.to_fg_str()) == color)
+                .ok_or_else(|| invalid(format!("Invalid color: {line}")))?;
+            settings.evaporation_exempt_at = parse_list::<String>(values.next(), line)?
+                .iter()
+                .map(|entity_type| parse_entity_type(entity_type))
+                .collect::<Option<_>>()
+                .ok_or_else(|| invalid(format!("Invalid entity type: {line}")))?;
+            settings.boosts = parse_list::<String>(values.next(), line)?
+                .iter()
+                .map(|boost| {
+                    let (entity_type, boost) = boost.split_once(':')?;
+                    Some((parse_entity_type(entity_type)?, boost.parse().ok()?))
+                })
+                .collect::<Option<_>>()
+                .ok_or_else(|| invalid(format!("Invalid boost: {line}")))?;
+            store
+                .pheromone_channels
+                .register(settings)
+                .map_err(|error| invalid(format!("{error}: {line}")))?;
+        }
+        "pheromone" => {
+            let channel = parse_channel(&store.pheromone_channels, values.next(), line)?;
+            let pos = CoarsePositionComponent {
+                x: parse(values.next(), line)?,
+                y: parse(values.next(), line)?,
+            };
+            if !store.cell_is_in_world(&pos) {
+                return Err(invalid(format!("Pheromone outside of the world: {line}")));
+            }
+            let strength = parse(values.next(), line)?;
+            let generation = parse(values.next(), line)?;
+            store
+                .pheromone_field_mut(channel)
+                .add(&pos, strength, generation);
+        }
+        "entity" => {
+            let entity_id: EntityIndex = parse(values.next(), line)?;
+            let (generations, _) = store.slots();
+            if generations.get(entity_id.index()) != Some(&entity_id.generation()) {
+                return Err(invalid(format!("Entity doesn't match its slot: {line}")));
+            }
+            let entity_type = values
+                .next()
+                .and_then(parse_entity_type)
+                .ok_or_else(|| invalid(format!("Invalid entity type: {line}")))?;
+            store.restore_entity(entity_id, entity_type);
+        }
+        "position" => {
+            let entity_id = parse(values.next(), line)?;
+            let pos =
+                PositionComponent::new(parse(values.next(), line)?, parse(values.next(), line)?);
+            let direction = match values.next() {
+                Some(x) => Some(DirectionComponent {
+                    x: parse(Some(x), line)?,
+                    y: parse(values.next(), line)?,
+                }),
+                None => None,
+            };
+            if !store.is_in_world(&pos) {
+                return Err(invalid(format!("Position outside of the world: {line}")));
+            }
+            store.restore_position(entity_id, &pos, direction);
+        }
+        _ => load_component_line(store, line)?,
+    }
+
+    Ok(())
+}
+
+/// Writes every component of every entity in `store`.
+fn save_components<W: Write>(store: &EntityStore, w: &mut W) -> io::Result<()> {
+    for (entity_id, edible) in &store.edibles {
+        write!(w, "edible {} {}", id(*entity_id), edible.weight)?;
+        if let Some(items) = edible.items {
+            write!(w, " {items}")?;
+        }
+        writeln!(w)?;
+    }
+    for (entity_id, releasing) in store.releasing_pheromones.iter() {
+        writeln!(
+            w,
+            "releasing {} {} {}",
+            id(*entity_id),
+            releasing.ticks_left,
+            store.pheromone_channels.get(releasing.channel).name
+        )?;
+    }
+    for (entity_id, carrying) in &store.carrying_food {
+        writeln!(w, "carrying {} {}", id(*entity_id), carrying.amount)?;
+    }
+    for (entity_id, capacity) in store.capacities.iter() {
+        writeln!(w, "capacity {} {}", id(*entity_id), capacity.capacity)?;
+    }
+    for entity_id in store.builders.keys() {
+        writeln!(w, "builder {}", id(*entity_id))?;
+    }
+    for entity_id in store.impenetrables.keys() {
+        writeln!(w, "impenetrable {}", id(*entity_id))?;
+    }
+    for (entity_id, memory) in store.memories.iter() {
+        writeln!(
+            w,
+            "memory {} {}",
+            id(*entity_id),
+            list(
+                memory
+                    .pos_queue
+                    .iter()
+                    .map(|pos| format!("{}:{}", pos.x, pos.y))
+            )
+        )?;
+    }
+    for (entity_id, adventurous) in &store.adventurous {
+        writeln!(
+            w,
+            "adventurous {} {}",
+            id(*entity_id),
+            adventurous.ticks_left
+        )?;
+    }
+    for (entity_id, caste) in &store.castes {
+        writeln!(
+            w,
+            "caste {} {:?} {}",
+            id(*entity_id),
+            caste.caste,
+            caste.threshold
+        )?;
+    }
+    for (entity_id, tandem) in &store.tandems {
+        writeln!(
+            w,
+            "tandem {} {} {:?} {}",
+            id(*entity_id),
+            id(tandem.partner),
+            tandem.role,
+            tandem.waited
+        )?;
+    }
+    for (entity_id, known_food) in &store.known_food {
+        writeln!(
+            w,
+            "known_food {} {} {}",
+            id(*entity_id),
+            known_food.pos.x,
+            known_food.pos.y
+        )?;
+    }
+
+    Ok(())
+}
+
+fn load_component_line(store: &mut EntityStore, line: &str) -> io::Result<()> {
+    let mut values = line.split_whitespace();
+    match values.next().unwrap_or("") {
+        "edible" => {
+            let entity_id = parse(values.next(), line)?;
+            let weight = parse(values.next(), line)?;
+            if weight == 0 {
+                return Err(invalid(format!("Invalid weight: {line}")));
+            }
+            let items = values.next().map(|n| parse(Some(n), line)).transpose()?;
+            store
+                .edibles
+                .insert(entity_id, EdibleComponent { weight, items });
+        }
+        "releasing" => {
+            let entity_id = parse(values.next(), line)?;
+            let ticks_left = parse(values.next(), line)?;
+            let channel = parse_channel(&store.pheromone_channels, values.next(), line)?;
+            store.releasing_pheromones.insert(
+                entity_id,
+                ReleasingPheromoneComponent {
+                    ticks_left,
+                    channel,
+                },
+            );
+        }
+        "carrying" => {
+            store.carrying_food.insert(
+                parse(values.next(), line)?,
+                CarryingFoodComponent {
+                    amount: parse(values.next(), line)?,
+                },
+            );
+        }
+        "capacity" => {
+            store.capacities.insert(
+                parse(values.next(), line)?,
+                CapacityComponent {
+                    capacity: parse(values.next(), line)?,
+                },
+            );
+        }
+        "builder" => {
+            store
+                .builders
+                .insert(parse(values.next(), line)?, BuilderComponent {});
+        }
+        "impenetrable" => {
+            store
+                .impenetrables
+                .insert(parse(values.next(), line)?, ImpenetrableComponent {});
+        }
+        "memory" => {
+            let entity_id = parse(values.next(), line)?;
+            let memory = parse_memory(values.next(), line)?;
+            store.memories.insert(entity_id, memory);
+        }
+        "adventurous" => {
+            store.adventurous.insert(
+                parse(values.next(), line)?,
+                AdventurousComponent {
+                    ticks_left: parse(values.next(), line)?,
+                },
+            );
+        }
+        "caste" => {
+            let entity_id = parse(values.next(), line)?;
+            let caste = match values.next() {
+                Some("Scout") => Caste::Scout,
+                Some("Forager") => Caste::Forager,
+                Some("Reserve") => Caste::Reserve,
+                _ => return Err(invalid(format!("Invalid caste: {line}"))),
+            };
+            let threshold = parse(values.next(), line)?;
+            store
+                .castes
+                .insert(entity_id, CasteComponent { caste, threshold });
+        }
+        "tandem" => {
+            let entity_id = parse(values.next(), line)?;
+            let tandem = parse_tandem(&mut values, line)?;
+            store.tandems.insert(entity_id, tandem);
+        }
+        "known_food" => {
+            let entity_id = parse(values.next(), line)?;
+            let pos =
+                PositionComponent::new(parse(values.next(), line)?, parse(values.next(), line)?);
+            if !store.is_in_world(&pos) {
+                return Err(invalid(format!("Food outside of the world: {line}")));
+            }
+            store
+                .known_food
+                .insert(entity_id, KnownFoodComponent { pos });
+        }
+        _ => return Err(invalid(format!("Unknown line: {line}"))),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use crate::components::CoarsePositionComponent;
+use crate::entity_store::EntityIndex;
+use std::collections::BTreeMap;
+
+/// Entities on every coarse cell.
+pub enum SpatialIndex {
+    /// Cells of an unbounded world, created when something moves onto them.
+    Sparse(BTreeMap<CoarsePositionComponent, Vec<EntityIndex>>),
+    /// Flat row-major grid covering a bounded world.
+    Grid {
+        width: usize,
+        height: usize,
+        cells: Vec<Vec<EntityIndex>>,
+    },
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        SpatialIndex::Sparse(BTreeMap::new())
+    }
+}
+
+impl SpatialIndex {
+    pub fn grid(width: usize, height: usize) -> Self {
+        SpatialIndex::Grid {
+            width,
+            height,
+            cells: vec![vec![]; width * height],
+        }
+    }
+
+    fn grid_index(width: usize, height: usize, pos: &CoarsePositionComponent) -> Option<usize> {
+        let (x, y) = (pos.x as usize, pos.y as usize);
+        if x < width && y < height {
+            Some(y * width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Entities on `pos`, or None if there are none.
+    pub fn get(&self, pos: &CoarsePositionComponent) -> Option<&[EntityIndex]> {
+        let ids = match self {
+            SpatialIndex::Sparse(cells) => cells.get(pos),
+            SpatialIndex::Grid {
+                width,
+                height,
+                cells,
+            } => Self::grid_index(*width, *height, pos).map(|i| &cells[i]),
+        };
+
+        ids.filter(|ids| !ids.is_empty()).map(Vec::as_slice)
+    }
+
+    /// Whether `pos` can be inserted, which is always the case for sparse
+    /// indexes.
+    pub fn covers(&self, pos: &CoarsePositionComponent) -> bool {
+        match self {
+            SpatialIndex::Sparse(_) => true,
+            SpatialIndex::Grid { width, height, .. } => {
+                Self::grid_index(*width, *height, pos).is_some()
+            }
+        }
+    }
+
+    /// Panics if `pos` is outside of the grid, callers check `covers`
+    /// first for positions that weren't validated yet.
+    pub fn insert(&mut self, pos: CoarsePositionComponent, id: EntityIndex) {
+        match self {
+            SpatialIndex::Sparse(cells) => cells.entry(pos).or_default().push(id),
+            SpatialIndex::Grid {
+                width,
+                height,
+                cells,
+            } => {
+                let i = Self::grid_index(*width, *height, &pos)
+                    .unwrap_or_else(|| panic!("{:?} is outside of the world!", pos));
+                cells[i].push(id);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, pos: &CoarsePositionComponent, id: EntityIndex) {
+        match self {
+            SpatialIndex::Sparse(cells) => {
+                if let Some(ids) = cells.get_mut(pos) {
+                    ids.retain(|other| *other != id);
+
+                    if ids.is_empty() {
+                        cells.remove(pos);
+                    }
+                }
+            }
+            SpatialIndex::Grid {
+                width,
+                height,
+                cells,
+            } => {
+                if let Some(i) = Self::grid_index(*width, *height, pos) {
+                    cells[i].retain(|other| *other != id);
+                }
+            }
+        }
+    }
+
+    /// Non-empty cells with `min.x <= x <= max.x` and `min.y <= y <= max.y`,
+    /// row by row.
+    pub fn cells_in_rect<'a>(
+        &'a self,
+        min: &CoarsePositionComponent,
+        max: &CoarsePositionComponent,
+    ) -> Box<dyn Iterator<Item = (CoarsePositionComponent, &'a [EntityIndex])> + 'a> {
+        let (min_x, max_x) = (min.x, max.x);
+        match self {
+            SpatialIndex::Sparse(cells) => Box::new(
+                (min.y..=max.y)
+                    .flat_map(move |y| {
+                        cells.range(
+                            CoarsePositionComponent { x: min_x, y }..=CoarsePositionComponent {
+                                x: max_x,
+                                y,
+                            },
+                        )
+                    })
+                    .map(|(pos, ids)| (pos.clone(), ids.as_slice())),
+            ),
+            SpatialIndex::Grid {
+                width,
+                height,
+                cells,
+            } => {
+                let (width, height) = (*width as u64, *height as u64);
+                Box::new(
+                    (min.y..=max.y.min(height.saturating_sub(1)))
+                        .flat_map(move |y| {
+                            (min_x..=max_x.min(width.saturating_sub(1)))
+                                .map(move |x| CoarsePositionComponent { x, y })
+                        })
+                        .filter_map(move |pos| {
+                            let ids = cells.get((pos.y * width + pos.x) as usize)?;
+                            Some((pos, ids.as_slice()))
+                        })
+                        .filter(|(_, ids)| !ids.is_empty()),
+                )
+            }
+        }
+    }
+
+    /// Every non-empty cell.
+    pub fn cells(
+        &self,
+    ) -> Box<dyn Iterator<Item = (CoarsePositionComponent, &[EntityIndex])> + '_> {
+        match self {
+            SpatialIndex::Sparse(cells) => {
+                Box::new(cells.iter().map(|(pos, ids)| (pos.clone(), ids.as_slice())))
+            }
+            SpatialIndex::Grid { width, cells, .. } => {
+                let width = *width;
+                Box::new(
+                    cells
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, ids)| !ids.is_empty())
+                        .map(move |(i, ids)| {
+                            let pos = CoarsePositionComponent {
+                                x: (i % width) as u64,
+                                y: (i / width) as u64,
+                            };
+                            (pos, ids.as_slice())
+                        }),
+                )
+            }
+        }
+    }
+
+    /// Whether any cell holds `id`.
+    pub fn contains(&self, id: EntityIndex) -> bool {
+        match self {
+            SpatialIndex::Sparse(cells) => cells.values().any(|ids| ids.contains(&id)),
+            SpatialIndex::Grid { cells, .. } => cells.iter().any(|ids| ids.contains(&id)),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::channels::PheromoneChannel;
+use crate::components::CoarsePositionComponent;
+use crate::fields::PheromoneField;
+use crate::game::Game;
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// When `Game::run_until` stops. Ticks are counted from the start of the run.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StopCondition {
+    /// After this many ticks.
+    Ticks(u32),
+    /// Once the base holds at least this much food.
+    FoodInBase(u32),
+    /// Once every sugar source ran out.
+    SugarDepleted,
+    /// Once no ants are left. Ants don't die in the simulation itself,
+    /// so this only happens when they're destroyed from outside, e.g. by
+    /// a custom `System`, or when the world has no ants.
+    AntsDead,
+    /// Once the route, the cells with reinforced food or base trails,
+    /// hasn't changed for this many ticks.
+    Converged(u32),
+    /// Once the run took this long.
+    Timeout(Duration),
+}
+
+impl fmt::Display for StopCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopCondition::Ticks(ticks) => write!(f, "ran {ticks} ticks"),
+            StopCondition::FoodInBase(food) => write!(f, "base holds {food} food"),
+            StopCondition::SugarDepleted => write!(f, "sugar depleted"),
+            StopCondition::AntsDead => write!(f, "all ants dead"),
+            StopCondition::Converged(ticks) => write!(f, "route unchanged for {ticks} ticks"),
+            StopCondition::Timeout(timeout) => write!(f, "timed out after {timeout:?}"),
+        }
+    }
+}
+
+/// The condition that stopped a run, and after how many ticks.
+#[derive(Debug, PartialEq)]
+pub struct Stop {
+    pub condition: StopCondition,
+    pub tick: u32,
+}
+
+/// Tracks a run to tell when one of its conditions is met.
+pub struct StopConditions {
+    conditions: Vec<StopCondition>,
+    started: Instant,
+    tick: u32,
+    route: HashSet<CoarsePositionComponent>,
+    route_since: u32,
+}
+
+impl StopConditions {
+    /// A run without conditions never stops.
+    ///
+    /// # Errors
+    ///
+    /// If `conditions` is empty.
+    pub fn new(conditions: Vec<StopCondition>) -> Result<Self, String> {
+        if conditions.is_empty() {
+            return Err("A run needs a stop condition".to_owned());
+        }
+
+        Ok(Self {
+            conditions,
+            started: Instant::now(),
+            tick: 0,
+            route: HashSet::new(),
+            route_since: 0,
+        })
+    }
+
+    /// Trails are reinforced once they're stronger than what a single
+    /// ant releases.
+    fn route(game: &Game) -> HashSet<CoarsePositionComponent> {
+        [PheromoneChannel::FOOD, PheromoneChannel::BASE]
+            .iter()
+            .filter_map(|channel| game.entity_store.pheromone_fields.get(channel.index()))
+            .flat_map(PheromoneField::iter)
+            .filter(|(_, cell)| cell.intensity.strength > game.config.pheromone_strength)
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    /// Returns the first condition `game` meets. Call it before the
+    /// first tick and after every tick.
+    pub fn check(&mut self, game: &Game) -> Option<Stop> {
+        let route = Self::route(game);
+        if route != self.route {
+            self.route = route;
+            self.route_since = self.tick;
+        }
+
+        let tick = self.tick;
+        self.tick += 1;
+        let store = &game.entity_store;
+        self.conditions
+            .iter()
+            .find(|condition| match condition {
+                StopCondition::Ticks(ticks) => tick >= *ticks,
+                StopCondition::FoodInBase(food) => store.food_in_base >= *food,
+                StopCondition::SugarDepleted => store.sugars.is_empty(),
+                StopCondition::AntsDead => store.ants.is_empty(),
+                StopCondition::Converged(ticks) => {
+                    !self.route.is_empty() && tick - self.route_since >= *ticks
+                }
+                StopCondition::Timeout(timeout) => self.started.elapsed() >= *timeout,
+            })
+            .map(|condition| Stop {
+                condition: condition.clone(),
+                tick,
+            })
+    }
+}
+
+impl Game {
+    /// Ticks until one of `conditions` is met.
+    ///
+    /// # Errors
+    ///
+    /// Without ticking, if there are no conditions.
+    pub fn run_until(&mut self, conditions: Vec<StopCondition>) -> Result<Stop, String> {
+        let mut stop_conditions = StopConditions::new(conditions)?;
+        loop {
+            if let Some(stop) = stop_conditions.check(self) {
+                return Ok(stop);
+            }
+            self.tick();
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::entity_store::EntityIndex;
+use std::collections::BTreeMap;
+
+/// Operations every component storage supports, independent of the
+/// component type. Used to join storages of different components.
+pub trait AnyStorage {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn contains_key(&self, id: &EntityIndex) -> bool;
+    fn ids(&self) -> Box<dyn Iterator<Item = EntityIndex> + '_>;
+}
+
+/// Storage for the components of one type.
+pub trait ComponentStorage<T>: AnyStorage + Default {
+    fn get(&self, id: &EntityIndex) -> Option<&T>;
+    fn get_mut(&mut self, id: &EntityIndex) -> Option<&mut T>;
+    fn insert(&mut self, id: EntityIndex, component: T) -> Option<T>;
+    fn remove(&mut self, id: &EntityIndex) -> Option<T>;
+}
+
+/// Sparse storage, a good fit for components few entities have.
+pub type SparseStorage<T> = BTreeMap<EntityIndex, T>;
+
+impl<T> AnyStorage for SparseStorage<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn contains_key(&self, id: &EntityIndex) -> bool {
+        self.contains_key(id)
+    }
+
+    fn ids(&self) -> Box<dyn Iterator<Item = EntityIndex> + '_> {
+        Box::new(self.keys().copied())
+    }
+}
+
+impl<T> ComponentStorage<T> for SparseStorage<T> {
+    fn get(&self, id: &EntityIndex) -> Option<&T> {
+        self.get(id)
+    }
+
+    fn get_mut(&mut self, id: &EntityIndex) -> Option<&mut T> {
+        self.get_mut(id)
+    }
+
+    fn insert(&mut self, id: EntityIndex, component: T) -> Option<T> {
+        self.insert(id, component)
+    }
+
+    fn remove(&mut self, id: &EntityIndex) -> Option<T> {
+        self.remove(id)
+    }
+}
+
+/// Dense storage indexed by entity slot, a good fit for components
+/// most entities have. Iterates in the same order as `SparseStorage`.
+pub struct DenseStorage<T> {
+    slots: Vec<Option<(EntityIndex, T)>>,
+    len: usize,
+}
+
+impl<T> Default for DenseStorage<T> {
+    fn default() -> Self {
+        Self {
+            slots: vec![],
+            len: 0,
+        }
+    }
+}
+
+impl<T> DenseStorage<T> {
+    #[must_use]
+    pub fn get(&self, id: &EntityIndex) -> Option<&T> {
+        match self.slots.get(id.index()) {
+            Some(Some((slot_id, component))) if slot_id == id => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: &EntityIndex) -> Option<&mut T> {
+        match self.slots.get_mut(id.index()) {
+            Some(Some((slot_id, component))) if slot_id == id => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, id: EntityIndex, component: T) -> Option<T> {
+        if self.slots.len() <= id.index() {
+            self.slots.resize_with(id.index() + 1, || None);
+        }
+
+        // A stale entity in the slot is replaced, so the length only
+        // grows when the slot was empty
+        match self.slots[id.index()].replace((id, component)) {
+            Some((old_id, old)) if old_id == id => Some(old),
+            Some(_) => None,
+            None => {
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: &EntityIndex) -> Option<T> {
+        if self.contains_key(id) {
+            self.len -= 1;
+            self.slots[id.index()]
+                .take()
+                .map(|(_, component)| component)
+        } else {
+            None
+        }
+    }
+
+    #[must_use]
+    pub fn contains_key(&self, id: &EntityIndex) -> bool {
+        self.get(id).is_some()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&EntityIndex, &T)> {
+        self.slots
+            .iter()
+            .filter_map(|slot| slot.as_ref().map(|(id, component)| (id, component)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&EntityIndex, &mut T)> {
+        self.slots
+            .iter_mut()
+            .filter_map(|slot| slot.as_mut().map(|(id, component)| (&*id, component)))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &EntityIndex> {
+        self.iter().map(|(id, _)| id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.iter().map(|(_, component)| component)
+    }
+}
+
+impl<T> AnyStorage for DenseStorage<T> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn contains_key(&self, id: &EntityIndex) -> bool {
+        self.contains_key(id)
+    }
+
+    fn ids(&self) -> Box<dyn Iterator<Item = EntityIndex> + '_> {
+        Box::new(self.keys().copied())
+    }
+}
+
+impl<T> ComponentStorage<T> for DenseStorage<T> {
+    fn get(&self, id: &EntityIndex) -> Option<&T> {
+        self.get(id)
+    }
+
+    fn get_mut(&mut self, id: &EntityIndex) -> Option<&mut T> {
+        self.get_mut(id)
+    }
+
+    fn insert(&mut self, id: EntityIndex, component: T) -> Option<T> {
+        self.insert(id, component)
+    }
+
+    fn remove(&mut self, id: &EntityIndex) -> Option<T> {
+        self.remove(id)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut DenseStorage<T> {
+    type Item = (&'a EntityIndex, &'a mut T);
+    type IntoIter = Box<dyn Iterator<Item = Self::Item> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter_mut())
+    }
+}
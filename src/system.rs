@@ -0,0 +1,107 @@
+use crate::game::Game;
+use std::time::{Duration, Instant};
+
+/// One step of the tick pipeline.
+pub trait System {
+    fn name(&self) -> &str;
+    /// The scheduler is taken out of `game` while its systems run, so
+    /// `game.scheduler` is empty here and can't be changed.
+    fn run(&mut self, game: &mut Game);
+}
+
+struct ScheduledSystem {
+    system: Box<dyn System>,
+    enabled: bool,
+    last_duration: Duration,
+}
+
+/// Runs systems in the order they were registered in.
+#[derive(Default)]
+pub struct Scheduler {
+    systems: Vec<ScheduledSystem>,
+}
+
+impl Scheduler {
+    fn position(&self, name: &str) -> Option<usize> {
+        self.systems.iter().position(|s| s.system.name() == name)
+    }
+
+    fn insert(&mut self, index: usize, system: Box<dyn System>) {
+        assert!(
+            self.position(system.name()).is_none(),
+            "System {} is already scheduled!",
+            system.name()
+        );
+
+        self.systems.insert(
+            index,
+            ScheduledSystem {
+                system,
+                enabled: true,
+                last_duration: Duration::default(),
+            },
+        );
+    }
+
+    /// Runs `system` after all systems registered so far.
+    pub fn add(&mut self, system: Box<dyn System>) {
+        self.insert(self.systems.len(), system);
+    }
+
+    /// Runs `system` right before the one called `before`.
+    ///
+    /// # Panics
+    ///
+    /// If there is no system called `before`.
+    pub fn add_before(&mut self, before: &str, system: Box<dyn System>) {
+        let index = self
+            .position(before)
+            .unwrap_or_else(|| panic!("No system named {}!", before));
+        self.insert(index, system);
+    }
+
+    /// Runs `system` right after the one called `after`.
+    ///
+    /// # Panics
+    ///
+    /// If there is no system called `after`.
+    pub fn add_after(&mut self, after: &str, system: Box<dyn System>) {
+        let index = self
+            .position(after)
+            .unwrap_or_else(|| panic!("No system named {}!", after));
+        self.insert(index + 1, system);
+    }
+
+    /// # Panics
+    ///
+    /// If there is no system called `name`, or when called on
+    /// `game.scheduler` from inside a system, see `System::run`.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        let index = self
+            .position(name)
+            .unwrap_or_else(|| panic!("No system named {}!", name));
+        self.systems[index].enabled = enabled;
+    }
+
+    /// Names of all systems in the order they run in, and whether
+    /// they're enabled.
+    pub fn systems(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.systems.iter().map(|s| (s.system.name(), s.enabled))
+    }
+
+    /// How long every enabled system took during the last tick.
+    pub fn timings(&self) -> impl Iterator<Item = (&str, Duration)> {
+        self.systems
+            .iter()
+            .filter(|s| s.enabled)
+            .map(|s| (s.system.name(), s.last_duration))
+    }
+
+    pub fn run(&mut self, game: &mut Game) {
+        for scheduled in self.systems.iter_mut().filter(|s| s.enabled) {
+            let start = Instant::now();
+            scheduled.system.run(game);
+            scheduled.last_duration = start.elapsed();
+        }
+    }
+}
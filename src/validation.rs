@@ -0,0 +1,87 @@
+use crate::components::CoarsePositionComponent;
+use crate::entities::EntityType;
+use crate::entity_store::EntityIndex;
+use std::fmt;
+
+/// An invariant of the `EntityStore` that doesn't hold.
+#[derive(Debug, PartialEq)]
+pub enum Violation {
+    /// An entity has a position on `cell` but the lookup doesn't list it there.
+    MissingFromLookup {
+        id: EntityIndex,
+        cell: CoarsePositionComponent,
+    },
+    /// The lookup lists an entity on `cell` that isn't positioned there.
+    StaleInLookup {
+        id: EntityIndex,
+        cell: CoarsePositionComponent,
+    },
+    /// The lookup lists an entity on `cell` more than once.
+    DuplicateInLookup {
+        id: EntityIndex,
+        cell: CoarsePositionComponent,
+    },
+    /// An entity's type doesn't match the entity maps it is in.
+    WrongEntityMaps {
+        id: EntityIndex,
+        entity_type: Option<EntityType>,
+        maps: Vec<EntityType>,
+    },
+    /// A handle of a destroyed entity is still in use.
+    StaleHandle { id: EntityIndex },
+    /// A component or a tandem partner refers to an entity that doesn't exist.
+    MissingEntity { id: EntityIndex },
+    /// A slot is free for reuse while an entity still lives in it.
+    FreeSlotInUse { id: EntityIndex },
+    /// An edible weighs nothing, so it can't be divided into items.
+    WeightlessEdible { id: EntityIndex },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::MissingFromLookup { id, cell } => {
+                write!(f, "entity {id} on {cell:?} is missing from the lookup")
+            }
+            Violation::StaleInLookup { id, cell } => {
+                write!(
+                    f,
+                    "entity {id} is in the lookup on {cell:?} but isn't there"
+                )
+            }
+            Violation::DuplicateInLookup { id, cell } => {
+                write!(f, "entity {id} is in the lookup on {cell:?} twice")
+            }
+            Violation::WrongEntityMaps {
+                id,
+                entity_type,
+                maps,
+            } => write!(
+                f,
+                "entity {id} of type {entity_type:?} is in the maps of {maps:?}"
+            ),
+            Violation::StaleHandle { id } => {
+                write!(f, "entity {}.{} was destroyed", id, id.generation())
+            }
+            Violation::MissingEntity { id } => {
+                write!(
+                    f,
+                    "entity {}.{} is referenced but doesn't exist",
+                    id,
+                    id.generation()
+                )
+            }
+            Violation::FreeSlotInUse { id } => {
+                write!(
+                    f,
+                    "slot {id} is free but entity {}.{} lives in it",
+                    id,
+                    id.generation()
+                )
+            }
+            Violation::WeightlessEdible { id } => {
+                write!(f, "edible {}.{} weighs nothing", id, id.generation())
+            }
+        }
+    }
+}
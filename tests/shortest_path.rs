@@ -0,0 +1,64 @@
+extern crate ants;
+
+use ants::components::PositionComponent;
+use ants::{Game, GameBuilder, PheromoneChannel};
+
+const WIDTH: f64 = 7.0;
+const HEIGHT: f64 = 4.0;
+
+/// Base and sugar on row 1. A wall on row 2 separates the straight route
+/// over rows 0 and 1 from a detour over row 3.
+fn detour_game(seed: u64) -> Game {
+    let mut builder = GameBuilder::new(WIDTH, HEIGHT);
+    builder.seed(seed);
+    for i in 0..10 {
+        builder.ant(0.5, 0.5 + f64::from(i % 2));
+    }
+
+    builder
+        .base(0.5, 1.5)
+        .sugar(WIDTH - 0.5, 1.5)
+        .wall_line((1.5, 2.5), (5.5, 2.5))
+        .build()
+        .unwrap()
+}
+
+fn trail_strength(game: &Game, y: f64) -> u32 {
+    (1..6)
+        .filter_map(|x| {
+            game.entity_store.get_pheromone_with_type_at(
+                &PositionComponent::new(f64::from(x) + 0.5, y),
+                PheromoneChannel::FOOD,
+            )
+        })
+        .map(|cell| cell.intensity.strength)
+        .sum()
+}
+
+/// Like real colonies, ants sometimes settle on the detour, but most
+/// runs should converge on the straight route.
+#[test]
+fn test_shortest_path_is_found() {
+    let runs = 20;
+    let mut straight_wins = 0;
+    for seed in 0..runs {
+        let mut game = detour_game(seed);
+        for _ in 0..300 {
+            game.tick();
+        }
+        assert!(game.entity_store.food_in_base > 0);
+
+        let straight = trail_strength(&game, 0.5) + trail_strength(&game, 1.5);
+        let detour = trail_strength(&game, 3.5);
+        if straight > detour {
+            straight_wins += 1;
+        }
+    }
+
+    assert!(
+        straight_wins >= runs * 2 / 3,
+        "straight route won {} of {} runs",
+        straight_wins,
+        runs
+    );
+}
@@ -0,0 +1,34 @@
+use std::process::Command;
+
+/// Food in base reported for every value of a swept parameter.
+fn swept_food(param: &str, walls: bool) -> Vec<String> {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ants"));
+    command.args(["sweep", "--ticks", "100", "--seed", "3", "--param", param]);
+    if walls {
+        command.arg("--walls");
+    }
+
+    let output = command.output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            line.split_whitespace()
+                .find(|field| field.starts_with("food="))
+                .unwrap()
+                .to_owned()
+        })
+        .collect()
+}
+
+#[test]
+fn channel_parameters_change_the_outcome() {
+    let food = swept_food("evaporation_rate=1,8", false);
+    assert_eq!(food.len(), 2);
+    assert_ne!(food[0], food[1]);
+
+    // Ants rarely run into dead ends without walls
+    let food = swept_food("repellent_evaporation_rate=1,32", true);
+    assert_ne!(food[0], food[1]);
+}
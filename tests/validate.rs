@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Exit code of `ants validate` with `args`.
+fn validate(args: &[&str]) -> i32 {
+    Command::new(env!("CARGO_BIN_EXE_ants"))
+        .arg("validate")
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+/// A config file with `contents`, named after the test writing it.
+fn config(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ants-validate-{name}.cfg"));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn scenarios_are_checked_without_running() {
+    assert_eq!(validate(&["--walls"]), 0);
+
+    let declared = config("declared", "channels = alarm:4:red\n");
+    assert_eq!(validate(&["--config", declared.to_str().unwrap()]), 0);
+
+    for (name, contents) in [
+        ("ticks", "pheromone_ticks = 0\n"),
+        ("resolution", "position_resolution = 3\n"),
+        ("channel", "channels = food:1:red\n"),
+    ] {
+        let invalid = config(name, contents);
+        assert_eq!(validate(&["--config", invalid.to_str().unwrap()]), 2);
+    }
+
+    assert_eq!(validate(&["--load", "missing-save.txt"]), 2);
+}